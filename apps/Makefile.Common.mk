@@ -12,6 +12,19 @@ include $(TOCK_APPS_DIR)/Makefile.$(TOCK_PLATFORM).mk
 
 ELF2TBF ?= $(TOCK_APPS_DIR)/../build/elf2tbf
 
+# Memory the app asks the kernel for beyond its data and BSS
+STACK_SIZE ?= 2048
+APP_HEAP_SIZE ?= 1024
+KERNEL_HEAP_SIZE ?= 1024
+# Callbacks the kernel queues for the app, 0 for the kernel's default
+CALLBACK_QUEUE_LEN ?= 0
+# Scheduling priority, higher runs first
+PRIORITY ?= 0
+# Period and CPU budget per period in microseconds, 0 for a non-periodic app
+PERIOD ?= 0
+BUDGET ?= 0
+ELF2TBF_FLAGS ?= --name $(APP) --stack $(STACK_SIZE) --app-heap $(APP_HEAP_SIZE) --kernel-heap $(KERNEL_HEAP_SIZE) --callback-queue $(CALLBACK_QUEUE_LEN) --priority $(PRIORITY) --period $(PERIOD) --budget $(BUDGET)
+
 TOCK_DIR = $(TOCK_APPS_DIR)/../src
 
 # XXX FIXME extern stuff
@@ -65,7 +78,7 @@ $(TOCK_APP_LIBS_DIR)/%.o: $(TOCK_APPS_DIR)/libs/%.s | $(TOCK_APP_LIBS_DIR)
 $(TOCK_APP_BUILD_DIR)/$(APP).bin: $(TOCK_APP_BUILD_DIR)/$(APP).elf
 	$(TRACE_BIN)
 	$(Q)$(SIZE) $(TOCK_APP_BUILD_DIR)/$(APP).elf
-	$(Q)$(ELF2TBF) -o $@ $<
+	$(Q)$(ELF2TBF) $(ELF2TBF_FLAGS) -o $@ $<
 
 $(TOCK_APP_BUILD_DIR)/$(APP).monolithic.o: $(TOCK_APP_BUILD_DIR)/$(APP).bin
 	$(TRACE_LD)
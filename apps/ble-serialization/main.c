@@ -143,5 +143,9 @@ int main () {
 
     // Setup reading from the temperature sensor
     tmp006_start_sampling(0x2, temp_callback, NULL);
+
+    while (1) {
+        wait();
+    }
 }
 
@@ -13,5 +13,9 @@ void nop(int x, int y, int z, void *ud) {}
 
 void main() {
   putnstr_async(hello, sizeof(hello), nop, NULL);
+
+  while (1) {
+    wait();
+  }
 }
 
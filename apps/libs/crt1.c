@@ -16,38 +16,38 @@ void _start();
 
 caddr_t _sbrk(int incr)
 {
-  return (void*)memop(1, incr);
+  int ret = memop(1, incr);
+  if (ret < 0) {
+    errno = ENOMEM;
+    return (caddr_t) -1;
+  }
+  return (caddr_t) ret;
 }
 
 int brk(void* memory_break) {
   return memop(0, (int)memory_break);
 }
 
-#ifndef STACK_SIZE
-#define STACK_SIZE 2048
-#endif
-
 __attribute__ ((section(".start"), used, naked))
-void _start(void* mem_start,
-    __attribute__((unused))void* app_memory_break,
+void _start(__attribute__((unused))void* mem_start,
+    void* app_memory_break,
     __attribute__((unused))void* kernel_memory_break) {
   void main();
 
   /* Setup the stack and heap.
-   * We setup the stack at the bottom of memory (directory after the GOT, data
-   * and BSS segments. The stack size is, therefore, fixed at load time, so
-   * adjust it appropriately using `-DSTACK_SIZE 1024` during compilation.
+   * The kernel places the stack at the bottom of memory (directly after the
+   * GOT, data and BSS segments and a guard region) and sets the initial
+   * memory break to the top of the stack. The stack size is, therefore, fixed
+   * at load time, so adjust it appropriately by setting `STACK_SIZE` in the
+   * app's Makefile, which passes it to the kernel in the app's header.
    *
    * The heap will begin directly above the stack, and grow upwards towards
    * kernel borrowed heap (which grows downwards from the top of memory).
    */
-  void* stack_bottom = mem_start + STACK_SIZE;
-  brk(stack_bottom);
-
-  __asm volatile ("mov sp, %0\n\t" : : "r" (stack_bottom));
+  __asm volatile ("mov sp, %0\n\t" : : "r" (app_memory_break));
 
   main();
 
-  while(1) { wait(); }
+  tock_exit(0);
 }
 
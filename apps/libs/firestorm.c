@@ -68,12 +68,16 @@ int timer_subscribe(subscribe_cb cb, void *userdata) {
   return subscribe(3, 0, cb, userdata);
 }
 
+int timer_start(uint32_t interval, uint32_t flags) {
+  return command2(3, 3, (int)interval, (int)flags);
+}
+
 int timer_oneshot(uint32_t interval) {
-  return command(3, 0, (int)interval);
+  return timer_start(interval, 0);
 }
 
 int timer_start_repeating(uint32_t interval) {
-  return command(3, 1, (int)interval);
+  return timer_start(interval, TIMER_REPEATING);
 }
 
 int timer_stop() {
@@ -24,6 +24,19 @@ void putnstr_async(const char* str, size_t len, subscribe_cb cb, void* userdata)
  */
 int timer_subscribe(subscribe_cb cb, void *userdata);
 
+// Flags for timer_start
+#define TIMER_REPEATING (1 << 0)
+
+/*
+ * Starts a timer
+ *
+ * interval_ms - the interval for the timer in milliseconds
+ * flags - TIMER_REPEATING to fire every interval, or 0 to fire once
+ *
+ * Side-effects: cancels any existing/outstanding timers
+ */
+int timer_start(uint32_t interval_ms, uint32_t flags);
+
 /*
  * Starts a repeating timer
  *
@@ -18,8 +18,7 @@ int gpio_toggle(GPIO_Pin_t pin) {
 }
 
 int gpio_enable_input(GPIO_Pin_t pin, GPIO_InputMode_t pin_config) {
-  uint32_t data = ((pin_config & 0xFF) << 8) | (pin & 0xFF);
-  return command(GPIO_DRIVER_NUM, 4, data);
+  return command2(GPIO_DRIVER_NUM, 9, pin, pin_config);
 }
 
 int gpio_read(GPIO_Pin_t pin) {
@@ -28,8 +27,11 @@ int gpio_read(GPIO_Pin_t pin) {
 
 int gpio_enable_interrupt(GPIO_Pin_t pin, GPIO_InputMode_t pin_config,
     GPIO_InterruptMode_t irq_config) {
-  uint32_t data = ((irq_config & 0xFF) << 16) | ((pin_config & 0xFF) << 8) | (pin & 0xFF);
-  return command(GPIO_DRIVER_NUM, 6, data);
+  int err = command2(GPIO_DRIVER_NUM, 9, pin, pin_config);
+  if (err < 0) {
+    return err;
+  }
+  return command2(GPIO_DRIVER_NUM, 10, pin, irq_config);
 }
 
 int gpio_disable_interrupt(GPIO_Pin_t pin) {
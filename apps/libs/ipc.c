@@ -0,0 +1,46 @@
+#include <string.h>
+#include "ipc.h"
+#include "tock.h"
+
+// Names are usually string literals in flash, which can't be allowed, so they
+// are copied here first.
+static char ipc_name[IPC_MAX_NAME_LEN];
+
+static int ipc_allow_name(const char* name) {
+  size_t len = strlen(name);
+  if (len > IPC_MAX_NAME_LEN) return TOCK_ESIZE;
+  memcpy(ipc_name, name, len);
+  return allow(IPC_DRIVER_NUM, 0, ipc_name, len);
+}
+
+int ipc_register_service(const char* name) {
+  int err = ipc_allow_name(name);
+  if (err < 0) return err;
+  return command(IPC_DRIVER_NUM, 0, 0);
+}
+
+int ipc_discover(const char* name) {
+  int err = ipc_allow_name(name);
+  if (err < 0) return err;
+  return command(IPC_DRIVER_NUM, 1, 0);
+}
+
+int ipc_register_service_callback(subscribe_cb callback, void* ud) {
+  return subscribe(IPC_DRIVER_NUM, 0, callback, ud);
+}
+
+int ipc_register_client_callback(subscribe_cb callback, void* ud) {
+  return subscribe(IPC_DRIVER_NUM, 1, callback, ud);
+}
+
+int ipc_share(int service_id, void* buffer, size_t len) {
+  return allow(IPC_DRIVER_NUM, service_id + 1, buffer, len);
+}
+
+int ipc_notify_service(int service_id) {
+  return command(IPC_DRIVER_NUM, 2, service_id);
+}
+
+int ipc_notify_client(int client_id) {
+  return command(IPC_DRIVER_NUM, 3, client_id);
+}
@@ -0,0 +1,43 @@
+#ifndef _IPC_H
+#define _IPC_H
+
+#include <unistd.h>
+#include "tock.h"
+
+#define IPC_DRIVER_NUM 7
+#define IPC_MAX_NAME_LEN 32
+
+#ifdef __cplusplus
+extern "C" {
+#endif
+
+// Registers the app as a service called `name`. Returns the service id.
+int ipc_register_service(const char* name);
+
+// Looks up the service called `name`. Returns its id, or a negative value if
+// no app registered that name.
+int ipc_discover(const char* name);
+
+// Called as callback(client_id, buffer_len, buffer_address) when a client
+// notifies the service.
+int ipc_register_service_callback(subscribe_cb callback, void* ud);
+
+// Called as callback(service_id, 0, 0) when a service notifies the client.
+int ipc_register_client_callback(subscribe_cb callback, void* ud);
+
+// Shares `buffer` with the service. The service gets read/write access to
+// exactly this buffer, so it must be a power of two of at least 32 bytes and
+// aligned to its size (or a run of whole eighths of such a block).
+int ipc_share(int service_id, void* buffer, size_t len);
+
+// Calls the service's callback.
+int ipc_notify_service(int service_id);
+
+// Calls a client's callback. Used by services.
+int ipc_notify_client(int client_id);
+
+#ifdef __cplusplus
+}
+#endif
+
+#endif // _IPC_H
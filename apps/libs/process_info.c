@@ -0,0 +1,12 @@
+#include "process_info.h"
+#include "tock.h"
+
+int process_info_count() {
+  return command(PROCESS_INFO_DRIVER_NUM, 0, 0);
+}
+
+int process_info_stats(int index, process_stats_t* stats) {
+  int err = allow(PROCESS_INFO_DRIVER_NUM, 0, stats, sizeof(*stats));
+  if (err < 0) return err;
+  return command(PROCESS_INFO_DRIVER_NUM, 1, index);
+}
@@ -0,0 +1,34 @@
+#ifndef _PROCESS_INFO_H
+#define _PROCESS_INFO_H
+
+#include <inttypes.h>
+#include "tock.h"
+
+#define PROCESS_INFO_DRIVER_NUM 8
+
+#ifdef __cplusplus
+extern "C" {
+#endif
+
+// Counters the kernel keeps for a process. Syscall counts are indexed by
+// syscall number: wait, subscribe, command, allow, memop, exit.
+typedef struct {
+  uint32_t timeslices;
+  uint32_t timeslice_expirations;
+  uint32_t syscalls[6];
+  uint32_t callbacks_queued;
+  uint32_t callbacks_dropped;
+} process_stats_t;
+
+// The number of process slots in the kernel.
+int process_info_count();
+
+// Reads the counters of the process in slot `index`. Returns a negative
+// value if there is no such process.
+int process_info_stats(int index, process_stats_t* stats);
+
+#ifdef __cplusplus
+}
+#endif
+
+#endif // _PROCESS_INFO_H
@@ -53,7 +53,7 @@ int _read(int fd, void *buf, uint32_t count)
 }
 void _exit(int status)
 {
-  while(666);
+  tock_exit(status);
 }
 void abort()
 {
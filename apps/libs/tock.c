@@ -5,10 +5,11 @@
 #include "firestorm.h"
 
 extern int __wait();
-extern int __command(uint32_t, uint32_t, int);
+extern int __command(uint32_t, uint32_t, int, int);
 extern int __allow();
 extern int __subscribe();
 extern int __memop(uint32_t, int);
+extern void __exit(int);
 
 void wait() {
   __wait();
@@ -27,7 +28,11 @@ int subscribe(uint32_t driver, uint32_t subscribe,
 
 
 int command(uint32_t driver, uint32_t command, int data) {
-  return __command(driver, command, data);
+  return __command(driver, command, data, 0);
+}
+
+int command2(uint32_t driver, uint32_t command, int arg1, int arg2) {
+  return __command(driver, command, arg1, arg2);
 }
 
 int allow(uint32_t driver, uint32_t allow, void* ptr, size_t size) {
@@ -38,3 +43,37 @@ int memop(uint32_t op_type, int arg1) {
   return __memop(op_type, arg1);
 }
 
+
+int dropped_callbacks() {
+  return __memop(2, 0);
+}
+
+void* tock_app_memory_begins_at() {
+  return (void*) __memop(3, 0);
+}
+
+void* tock_app_memory_ends_at() {
+  return (void*) __memop(4, 0);
+}
+
+void* tock_app_flash_begins_at() {
+  return (void*) __memop(5, 0);
+}
+
+int tock_app_flash_size() {
+  return __memop(6, 0);
+}
+
+void* tock_app_grant_begins_at() {
+  return (void*) __memop(7, 0);
+}
+
+void* tock_app_stack_limit() {
+  return (void*) __memop(8, 0);
+}
+
+void tock_exit(int status) {
+  __exit(status);
+  // The kernel never returns to an app that exited
+  while(1);
+}
@@ -9,20 +9,85 @@
 extern "C" {
 #endif
 
+// Values returned by subscribe, command, allow and memop. Anything
+// non-negative is success: either TOCK_SUCCESS or a value such as a reading or
+// an id. Errors are always negative. These match `ReturnCode` in the kernel's
+// hil::driver.
+#define TOCK_SUCCESS        0
+#define TOCK_FAIL          -1  // Generic failure
+#define TOCK_EBUSY         -2  // Busy with another request, try again later
+#define TOCK_EALREADY      -3  // The requested state is already in effect
+#define TOCK_EOFF          -4  // The device is off or not listening
+#define TOCK_ERESERVE      -5  // Touches memory or a resource the kernel owns
+#define TOCK_EINVAL        -6  // An argument is invalid
+#define TOCK_ESIZE         -7  // A buffer or length is the wrong size
+#define TOCK_ECANCEL       -8  // The operation was cancelled
+#define TOCK_ENOMEM        -9  // Out of memory
+#define TOCK_ENOSUPPORT   -10  // The driver doesn't support this operation
+#define TOCK_ENODEVICE    -11  // No driver with that number
+#define TOCK_EUNINSTALLED -12  // The device isn't installed
+#define TOCK_ENOACK       -13  // A packet was sent but not acknowledged
+#define TOCK_EPERM        -14  // The app isn't allowed to make the call
+
 typedef void (subscribe_cb)(int, int, int,void*);
 
 void wait();
 void wait_for(bool*);
 int command(uint32_t driver, uint32_t command, int data);
+// Like command, for commands that take two arguments.
+int command2(uint32_t driver, uint32_t command, int arg1, int arg2);
 int subscribe(uint32_t driver, uint32_t subscribe,
               subscribe_cb cb, void* userdata);
+
+// subscribe, command and allow fail with TOCK_EPERM if the board doesn't let
+// the app use the driver, or if its policy for the app rules out that minor
+// number or limits how often the app may issue the command. A policy can
+// instead fault the app.
+//
+// Besides errors returned by the driver, allow fails with:
+// TOCK_ENODEVICE: no such driver
+// TOCK_EINVAL: the buffer is not in the app's memory
+// TOCK_ERESERVE: the buffer extends past the app's memory break (into memory
+//     the kernel owns or may claim)
+// TOCK_ESIZE: the buffer wraps around the end of the address space
 int allow(uint32_t driver, uint32_t allow, void* ptr, size_t size);
 
 // op_type can be:
 // 0: brk, arg1 is pointer to new memory break
-// 1: sbrk, arg1 is increment to increase/decrease memory break
+// 1: sbrk, arg1 is increment to increase/decrease memory break, returns the
+//    old break
+// brk and sbrk fail with TOCK_EINVAL if the new break is outside the app's
+// memory, TOCK_ENOMEM if it runs into memory the kernel owns, or TOCK_EBUSY
+// if it would drop below the end of a buffer the app has passed to allow.
+// 2: returns the number of callbacks the kernel dropped because the app's
+//    callback queue was full since the last call, arg1 is ignored
+// 3-8 return addresses and sizes of the app's memory, arg1 is ignored:
+// 3: start of the app's RAM
+// 4: end of the app's RAM
+// 5: start of the app's image in flash
+// 6: size of the app's image in flash
+// 7: start of the grant region, the kernel-owned memory at the top of the
+//    app's RAM. The memory break can't move past it.
+// 8: the lowest address the stack may grow down to before it overflows
 int memop(uint32_t op_type, int arg1);
 
+// memop(2, 0): callbacks dropped since the last call
+int dropped_callbacks();
+
+// memop(3, 0) through memop(8, 0)
+void* tock_app_memory_begins_at();
+void* tock_app_memory_ends_at();
+void* tock_app_flash_begins_at();
+int tock_app_flash_size();
+void* tock_app_grant_begins_at();
+void* tock_app_stack_limit();
+
+// Ends the app. The kernel releases the memory drivers hold for it and never
+// runs it again unless it is restarted, and its RAM goes back to the kernel.
+// Returning from main and newlib's exit() both end up here, so apps that
+// handle callbacks must keep calling wait().
+void tock_exit(int status) __attribute__((noreturn));
+
 #ifdef __cplusplus
 }
 #endif
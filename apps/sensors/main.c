@@ -29,5 +29,7 @@ int main() {
 
   tmp006_start_sampling(0x2, temp_callback, NULL);
 
-  return 0;
+  while (1) {
+    wait();
+  }
 }
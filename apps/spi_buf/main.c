@@ -46,4 +46,8 @@ int main(void) {
 	}
 
         spi_read_write(wbuf, rbuf, BUF_SIZE, write_cb, NULL);
+
+	while (1) {
+		wait();
+	}
 }
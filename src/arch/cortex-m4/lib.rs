@@ -6,4 +6,5 @@
 extern crate common;
 
 pub mod mpu;
+pub mod scb;
 
@@ -92,15 +92,25 @@ impl MPU {
     /// `start_addr`: the region base address. Lower bits will be masked
     ///               according to the region size. 
     /// `len`       : region size as a function 2^(len + 1)
+    /// `srd`       : subregion disable bits. Bit `i` disables the `i`th eighth
+    ///               of the region. Ignored for regions smaller than 256 bytes.
     /// `execute`   : whether to enable code execution from this region
     /// `ap`        : access permissions as defined in Table 4.47 of the user
     ///               guide.
     pub fn set_mpu(&mut self, region_num: u32, start_addr: u32, len: u32,
-                  execute: bool, ap: u32) {
+                  srd: u8, execute: bool, ap: u32) {
         let regs = unsafe { &*self.0 };
         regs.region_base_address.set(region_num | 1 << 4 | start_addr);
         let xn = if execute { 0 } else { 1 };
-        regs.region_attributes_and_size.set(1 | len << 1 | ap << 24 | xn << 28);
+        regs.region_attributes_and_size.set(
+            1 | len << 1 | (srd as u32) << 8 | ap << 24 | xn << 28);
+    }
+
+    /// Disables the given MPU region number.
+    pub fn disable_region(&mut self, region_num: u32) {
+        let regs = unsafe { &*self.0 };
+        regs.region_base_address.set(region_num | 1 << 4);
+        regs.region_attributes_and_size.set(0);
     }
 }
 
@@ -0,0 +1,115 @@
+//! Fault status registers of the System Control Block (SCB).
+
+use core::fmt;
+use common::volatile_cell::VolatileCell;
+
+#[repr(C,packed)]
+struct Registers {
+    /// Configurable Fault Status Register. Made up of the MemManage (bits
+    /// 7:0), BusFault (bits 15:8) and UsageFault (bits 31:16) status
+    /// registers. Bits are cleared by writing a one to them.
+    cfsr: VolatileCell<u32>,
+
+    /// HardFault Status Register. Write one to clear.
+    hfsr: VolatileCell<u32>,
+
+    /// Debug Fault Status Register.
+    _dfsr: VolatileCell<u32>,
+
+    /// MemManage Fault Address Register. Valid if MMARVALID is set in CFSR.
+    mmfar: VolatileCell<u32>,
+
+    /// BusFault Address Register. Valid if BFARVALID is set in CFSR.
+    bfar: VolatileCell<u32>
+}
+
+const SCB_FAULT_BASE_ADDRESS: *const Registers = 0xE000ED28 as *const Registers;
+
+/// Names and descriptions of the CFSR bits, as defined in 4.3.10 of the
+/// Cortex-M4 user guide.
+const CFSR_BITS: [(u32, &'static str, &'static str); 16] = [
+    (0,  "IACCVIOL",    "instruction access violation"),
+    (1,  "DACCVIOL",    "data access violation"),
+    (3,  "MUNSTKERR",   "MemManage fault on unstacking for exception return"),
+    (4,  "MSTKERR",     "MemManage fault on stacking for exception entry"),
+    (5,  "MLSPERR",     "MemManage fault during floating-point lazy stacking"),
+    (8,  "IBUSERR",     "instruction bus error"),
+    (9,  "PRECISERR",   "precise data bus error"),
+    (10, "IMPRECISERR", "imprecise data bus error"),
+    (11, "UNSTKERR",    "BusFault on unstacking for exception return"),
+    (12, "STKERR",      "BusFault on stacking for exception entry"),
+    (13, "LSPERR",      "BusFault during floating-point lazy stacking"),
+    (16, "UNDEFINSTR",  "undefined instruction"),
+    (17, "INVSTATE",    "invalid state (e.g. executing with the Thumb bit clear)"),
+    (18, "INVPC",       "invalid PC load by EXC_RETURN"),
+    (24, "UNALIGNED",   "unaligned access"),
+    (25, "DIVBYZERO",   "divide by zero"),
+];
+
+const MMARVALID: u32 = 1 << 7;
+const BFARVALID: u32 = 1 << 15;
+
+const HFSR_VECTTBL: u32 = 1 << 1;
+const HFSR_FORCED: u32 = 1 << 30;
+
+/// A snapshot of the fault status registers.
+#[derive(Copy,Clone)]
+pub struct FaultStatus {
+    pub cfsr: u32,
+    pub hfsr: u32,
+    pub mmfar: u32,
+    pub bfar: u32
+}
+
+impl FaultStatus {
+    /// Reads the fault status registers and clears them so the next fault
+    /// starts from a clean slate.
+    pub unsafe fn read_and_clear() -> FaultStatus {
+        let regs = &*SCB_FAULT_BASE_ADDRESS;
+        let status = FaultStatus {
+            cfsr: regs.cfsr.get(),
+            hfsr: regs.hfsr.get(),
+            mmfar: regs.mmfar.get(),
+            bfar: regs.bfar.get()
+        };
+        regs.cfsr.set(status.cfsr);
+        regs.hfsr.set(status.hfsr);
+        status
+    }
+
+    /// The faulting data address, if the hardware recorded one.
+    pub fn fault_address(&self) -> Option<u32> {
+        if self.cfsr & MMARVALID != 0 {
+            Some(self.mmfar)
+        } else if self.cfsr & BFARVALID != 0 {
+            Some(self.bfar)
+        } else {
+            None
+        }
+    }
+}
+
+impl fmt::Display for FaultStatus {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        try!(write!(f, "\tCFSR  0x{:08x}\r\n\tHFSR  0x{:08x}\r\n",
+                    self.cfsr, self.hfsr));
+        for &(bit, name, description) in CFSR_BITS.iter() {
+            if self.cfsr & (1 << bit) != 0 {
+                try!(write!(f, "\t  {}: {}\r\n", name, description));
+            }
+        }
+        if self.hfsr & HFSR_VECTTBL != 0 {
+            try!(write!(f, "\t  VECTTBL: BusFault on vector table read\r\n"));
+        }
+        if self.hfsr & HFSR_FORCED != 0 {
+            try!(write!(f, "\t  FORCED: escalated from a configurable fault\r\n"));
+        }
+        if self.cfsr & MMARVALID != 0 {
+            try!(write!(f, "\tMMFAR 0x{:08x}\r\n", self.mmfar));
+        }
+        if self.cfsr & BFARVALID != 0 {
+            try!(write!(f, "\tBFAR  0x{:08x}\r\n", self.bfar));
+        }
+        Ok(())
+    }
+}
@@ -53,6 +53,7 @@ extern {
     // Defined in src/arch/cortex-m4/ctx_switch.S
     fn SVC_Handler();
     fn systick_handler();
+    fn fault_handler();
 
     fn generic_isr();
 
@@ -67,10 +68,10 @@ extern {
 pub static BASE_VECTORS: [unsafe extern fn(); 16] = [
     _estack, reset_handler,
     /* NMI */           unhandled_interrupt,
-    /* Hard Fault */    hard_fault_handler,
-    /* MemManage */     unhandled_interrupt,
-    /* BusFault */      unhandled_interrupt,
-    /* UsageFault*/     unhandled_interrupt,
+    /* Hard Fault */    fault_handler,
+    /* MemManage */     fault_handler,
+    /* BusFault */      fault_handler,
+    /* UsageFault*/     fault_handler,
     unhandled_interrupt, unhandled_interrupt, unhandled_interrupt,
     unhandled_interrupt,
     /* SVC */           SVC_Handler,
@@ -198,7 +199,10 @@ unsafe extern "C" fn reset_handler() {
     main();
 }
 
-unsafe extern "C" fn hard_fault_handler() {
+/// Handles faults in the kernel. Faults in processes are caught by
+/// `fault_handler` in `ctx_switch.S` before reaching here.
+#[no_mangle]
+pub unsafe extern "C" fn hard_fault_handler() {
     use core::intrinsics::offset;
 
     let faulting_stack: *mut u32;
@@ -15,6 +15,15 @@ impl<'a, T: Copy> RingBuffer<'a, T> {
             ring: ring
         }
     }
+
+    /// The number of elements queued.
+    pub fn len(&self) -> usize {
+        unsafe {
+            let head = volatile_load(&self.head);
+            let tail = volatile_load(&self.tail);
+            (tail + self.ring.len() - head) % self.ring.len()
+        }
+    }
 }
 
 impl<'a, T: Copy> queue::Queue<T> for RingBuffer<'a, T> {
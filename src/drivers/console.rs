@@ -1,6 +1,7 @@
+use core::cell::Cell;
 use common::take_cell::TakeCell;
 use process::{AppId, AppSlice, Container, Callback, Shared};
-use hil::Driver;
+use hil::{Driver, ReturnCode};
 use hil::uart::{UART, Client};
 
 pub struct App {
@@ -29,21 +30,48 @@ impl Default for App {
 
 pub static mut WRITE_BUF : [u8; 64] = [0; 64];
 
+/// Lines typed on the console that start with this byte are for the kernel
+/// shell rather than apps.
+pub const SHELL_PREFIX : u8 = b'!';
+
+/// Longest kernel shell line kept; the rest of a longer line is dropped.
+pub const SHELL_LINE_LEN : usize = 32;
+
+pub static mut SHELL_LINE_BUF : [u8; SHELL_LINE_LEN] = [0; SHELL_LINE_LEN];
+
+#[derive(Copy, Clone, PartialEq, Eq)]
+enum ShellState {
+    Idle,
+    /// Receiving a shell line.
+    Reading,
+    /// A complete line is waiting for the kernel to take it.
+    Ready
+}
+
 pub struct Console<'a, U: UART + 'a> {
     uart: &'a U,
     apps: Container<App>,
     in_progress: TakeCell<AppId>,
-    buffer: TakeCell<&'static mut [u8]>
+    buffer: TakeCell<&'static mut [u8]>,
+    shell_line: TakeCell<&'static mut [u8]>,
+    shell_len: Cell<usize>,
+    shell_state: Cell<ShellState>,
+    at_line_start: Cell<bool>
 }
 
 impl<'a, U: UART> Console<'a, U> {
     pub const fn new(uart: &'a U, buffer: &'static mut [u8],
+                     shell_line: &'static mut [u8],
                      container: Container<App>) -> Console<'a, U> {
         Console {
             uart: uart,
             apps: container,
             in_progress: TakeCell::empty(),
-            buffer: TakeCell::new(buffer)
+            buffer: TakeCell::new(buffer),
+            shell_line: TakeCell::new(shell_line),
+            shell_len: Cell::new(0),
+            shell_state: Cell::new(ShellState::Idle),
+            at_line_start: Cell::new(true)
         }
     }
 
@@ -51,36 +79,80 @@ impl<'a, U: UART> Console<'a, U> {
         self.uart.enable_tx();
         self.uart.enable_rx();
     }
+
+    /// Whether app output is being sent. The UART has the write buffer until
+    /// it is done, even if the app that wrote has since exited.
+    pub fn transmitting(&self) -> bool {
+        self.buffer.is_none()
+    }
+
+    /// Copies the last complete kernel shell line, without the prefix, into
+    /// `line` and returns its length. Returns `None` if no line is waiting.
+    pub fn take_shell_line(&self, line: &mut [u8]) -> Option<usize> {
+        if self.shell_state.get() != ShellState::Ready {
+            return None;
+        }
+        let len = self.shell_len.get();
+        self.shell_state.set(ShellState::Idle);
+        self.shell_line.map(|shell_line| {
+            let mut copied = 0;
+            for (dst, src) in line.iter_mut().zip(shell_line[..len].iter()) {
+                *dst = *src;
+                copied += 1;
+            }
+            copied
+        })
+    }
+
+    fn read_shell(&self, c: u8) {
+        match c as char {
+            '\r' => {},
+            '\n' => {
+                self.shell_state.set(ShellState::Ready);
+                self.at_line_start.set(true);
+            },
+            _ => {
+                let len = self.shell_len.get();
+                self.shell_line.map(|shell_line| {
+                    if len < shell_line.len() {
+                        shell_line[len] = c;
+                        self.shell_len.set(len + 1);
+                    }
+                });
+            }
+        }
+    }
 }
 
 impl<'a, U: UART> Driver for Console<'a, U> {
     fn allow(&self, appid: AppId,
-             allow_num: usize, slice: AppSlice<Shared, u8>) -> isize {
+             allow_num: usize, slice: AppSlice<Shared, u8>) -> ReturnCode {
         match allow_num {
             0 => {
                 self.apps.enter(appid, |app, _| {
                     app.read_buffer = Some(slice);
                     app.read_idx = 0;
-                    0
-                }).unwrap_or(-1)
+                    ReturnCode::SUCCESS
+                }).unwrap_or_else(|err| err.into())
             },
             1 => {
                 self.apps.enter(appid, |app, _| {
                     app.write_buffer = Some(slice);
-                    0
-                }).unwrap_or(-1)
+                    ReturnCode::SUCCESS
+                }).unwrap_or_else(|err| err.into())
             }
-            _ => -1
+            _ => ReturnCode::ENOSUPPORT
         }
     }
 
-    fn subscribe(&self, subscribe_num: usize, callback: Callback) -> isize {
+    fn subscribe(&self, subscribe_num: usize, callback: Callback)
+            -> ReturnCode {
         match subscribe_num {
             0 /* read line */ => {
                 self.apps.enter(callback.app_id(), |app, _| {
                     app.read_callback = Some(callback);
-                    0
-                }).unwrap_or(-1)
+                    ReturnCode::SUCCESS
+                }).unwrap_or_else(|err| err.into())
             },
             1 /* putstr/write_done */ => {
                 self.apps.enter(callback.app_id(), |app, _| {
@@ -88,7 +160,10 @@ impl<'a, U: UART> Driver for Console<'a, U> {
                         Some(slice) => {
                             app.write_callback = Some(callback);
                             app.write_len = slice.len();
-                            if self.in_progress.is_none() {
+                            // The buffer may still be out with the write
+                            // of an app that exited
+                            if self.in_progress.is_none() &&
+                                    !self.buffer.is_none() {
                                 self.in_progress.replace(callback.app_id());
                                 self.buffer.take().map(|buffer| {
                                     for (i, c) in slice.as_ref().iter().enumerate() {
@@ -103,22 +178,38 @@ impl<'a, U: UART> Driver for Console<'a, U> {
                                 app.pending_write = true;
                                 app.write_buffer = Some(slice);
                             }
-                            0
+                            ReturnCode::SUCCESS
                         },
-                        None => -1
+                        None => ReturnCode::EINVAL
                     }
-                }).unwrap_or(-1)
+                }).unwrap_or_else(|err| err.into())
             },
-            _ => -1
+            _ => ReturnCode::ENOSUPPORT
         }
     }
 
-    fn command(&self, cmd_num: usize, arg1: usize, _: AppId) -> isize {
+    fn command(&self, cmd_num: usize, arg1: usize, _: AppId) -> ReturnCode {
         match cmd_num {
-            0 /* putc */ => { self.uart.send_byte(arg1 as u8); 1 },
-            _ => -1
+            0 /* putc */ => {
+                self.uart.send_byte(arg1 as u8);
+                ReturnCode::SuccessWithValue { value: 1 }
+            },
+            _ => ReturnCode::ENOSUPPORT
         }
     }
+
+    fn app_exited(&self, appid: AppId) {
+        // If the app's write is still being sent the buffer comes back in
+        // `write_done`, which then starts the next pending write
+        let writing = self.in_progress.map_or(false, |id| *id == appid);
+        if writing {
+            self.in_progress.take();
+        }
+    }
+
+    fn grant_bytes(&self, appid: AppId) -> usize {
+        self.apps.grant_bytes(appid)
+    }
 }
 
 impl<'a, U: UART> Client for Console<'a, U> {
@@ -155,7 +246,7 @@ impl<'a, U: UART> Client for Console<'a, U> {
                 } else {
                     false
                 }
-            });
+            }).unwrap_or(false);
             if started_tx {
                 break;
             }
@@ -163,6 +254,20 @@ impl<'a, U: UART> Client for Console<'a, U> {
     }
 
     fn read_done(&self, c: u8) {
+        // Lines starting with the shell prefix go to the kernel, unless it
+        // hasn't taken the previous one yet
+        if self.shell_state.get() == ShellState::Reading {
+            self.read_shell(c);
+            return;
+        }
+        if self.at_line_start.get() && c == SHELL_PREFIX &&
+                self.shell_state.get() == ShellState::Idle {
+            self.shell_len.set(0);
+            self.shell_state.set(ShellState::Reading);
+            return;
+        }
+        self.at_line_start.set(c == b'\n');
+
         match c as char {
             '\r' => {},
             '\n' => {
@@ -1,6 +1,6 @@
 use core::cell::Cell;
 use process::{AppId, Callback};
-use hil::Driver;
+use hil::{Driver, ReturnCode};
 use hil::gpio::{GPIOPin,InputMode,InterruptMode,Client};
 
 pub struct GPIO<'a, G: GPIOPin + 'a> {
@@ -16,47 +16,47 @@ impl<'a, G: GPIOPin> GPIO<'a, G> {
         }
     }
 
-    fn configure_input_pin(&self, pin_num: usize, config: usize) -> isize {
+    fn configure_input_pin(&self, pin_num: usize, config: usize) -> ReturnCode {
         let pins = self.pins.as_ref();
         match config {
             0 => {
                 pins[pin_num].enable_input(InputMode::PullUp);
-                0
+                ReturnCode::SUCCESS
             },
 
             1 => {
                 pins[pin_num].enable_input(InputMode::PullDown);
-                0
+                ReturnCode::SUCCESS
             },
 
             2 => {
                 pins[pin_num].enable_input(InputMode::PullNone);
-                0
+                ReturnCode::SUCCESS
             },
 
-            _ => -1
+            _ => ReturnCode::EINVAL
         }
     }
 
-    fn configure_interrupt(&self, pin_num: usize, config: usize) -> isize {
+    fn configure_interrupt(&self, pin_num: usize, config: usize) -> ReturnCode {
         let pins = self.pins.as_ref();
         match config {
             0 => {
                 pins[pin_num].enable_interrupt(pin_num, InterruptMode::Change);
-                0
+                ReturnCode::SUCCESS
             },
 
             1 => {
                 pins[pin_num].enable_interrupt(pin_num, InterruptMode::RisingEdge);
-                0
+                ReturnCode::SUCCESS
             },
 
             2 => {
                 pins[pin_num].enable_interrupt(pin_num, InterruptMode::FallingEdge);
-                0
+                ReturnCode::SUCCESS
             },
 
-            _ => -1
+            _ => ReturnCode::EINVAL
         }
     }
 }
@@ -75,60 +75,61 @@ impl<'a, G: GPIOPin> Client for GPIO<'a, G> {
 }
 
 impl<'a, G: GPIOPin> Driver for GPIO<'a, G> {
-    fn subscribe(&self, subscribe_num: usize, callback: Callback) -> isize {
+    fn subscribe(&self, subscribe_num: usize, callback: Callback)
+            -> ReturnCode {
         match subscribe_num {
             // subscribe to all pin interrupts
             // (no affect or reliance on individual pins being configured as interrupts)
             0 => {
                 self.callback.set(Some(callback));
-                0
+                ReturnCode::SUCCESS
             }
 
             // default
-            _ => -1
+            _ => ReturnCode::ENOSUPPORT
         }
     }
 
-    fn command(&self, command_num: usize, data: usize, _: AppId) -> isize {
+    fn command(&self, command_num: usize, data: usize, _: AppId) -> ReturnCode {
         let pins = self.pins.as_ref();
         match command_num {
             // enable output
             0 => {
                 if data >= pins.len() {
-                    -1
+                    ReturnCode::EINVAL
                 } else {
                     pins[data].enable_output();
-                    0
+                    ReturnCode::SUCCESS
                 }
             },
 
             // set pin
             1 => {
                 if data >= pins.len() {
-                    -1
+                    ReturnCode::EINVAL
                 } else {
                     pins[data].set();
-                    0
+                    ReturnCode::SUCCESS
                 }
             },
 
             // clear pin
             2  => {
                 if data >= pins.len() {
-                    -1
+                    ReturnCode::EINVAL
                 } else {
                     pins[data].clear();
-                    0
+                    ReturnCode::SUCCESS
                 }
             },
 
             // toggle pin
             3 => {
                 if data >= pins.len() {
-                    -1
+                    ReturnCode::EINVAL
                 } else {
                     pins[data].toggle();
-                    0
+                    ReturnCode::SUCCESS
                 }
             },
 
@@ -140,7 +141,7 @@ impl<'a, G: GPIOPin> Driver for GPIO<'a, G> {
                 let pin_num = data & 0xFF;
                 let pin_config = (data >> 8) & 0xFF;
                 if pin_num >= pins.len() {
-                    -1
+                    ReturnCode::EINVAL
                 } else {
                    let err_code = self.configure_input_pin(pin_num, pin_config);
                    err_code
@@ -150,10 +151,10 @@ impl<'a, G: GPIOPin> Driver for GPIO<'a, G> {
             // read input
             5 => {
                 if data >= pins.len() {
-                    -1
+                    ReturnCode::EINVAL
                 } else {
                     let pin_state = pins[data].read();
-                    pin_state as isize
+                    ReturnCode::SuccessWithValue { value: pin_state as usize }
                 }
             },
 
@@ -167,10 +168,10 @@ impl<'a, G: GPIOPin> Driver for GPIO<'a, G> {
                 let pin_config = (data >>  8) & 0xFF;
                 let irq_config = (data >> 16) & 0xFF;
                 if pin_num >= pins.len() {
-                    -1
+                    ReturnCode::EINVAL
                 } else {
                     let mut err_code = self.configure_input_pin(pin_num, pin_config);
-                    if err_code == 0 {
+                    if err_code == ReturnCode::SUCCESS {
                         err_code = self.configure_interrupt(pin_num, irq_config);
                     }
                     err_code
@@ -181,26 +182,57 @@ impl<'a, G: GPIOPin> Driver for GPIO<'a, G> {
             // (no affect or reliance on registered callback)
             7 => {
                 if data >= pins.len() {
-                    -1
+                    ReturnCode::EINVAL
                 } else {
                     pins[data].disable_interrupt();
                     pins[data].disable();
-                    0
+                    ReturnCode::SUCCESS
                 }
             },
 
             // disable pin
             8 => {
                 if data >= pins.len() {
-                    -1
+                    ReturnCode::EINVAL
                 } else {
                     pins[data].disable();
-                    0
+                    ReturnCode::SUCCESS
                 }
             }
 
             // default
-            _ => -1
+            _ => ReturnCode::ENOSUPPORT
+        }
+    }
+
+    fn command2(&self, command_num: usize, pin_num: usize, config: usize,
+                appid: AppId) -> ReturnCode {
+        if command_num < 9 {
+            return self.command(command_num, pin_num, appid);
+        }
+        if pin_num >= self.pins.len() {
+            return ReturnCode::EINVAL;
+        }
+        match command_num {
+            // enable and configure input, like 4 without packing
+            9 => self.configure_input_pin(pin_num, config),
+
+            // enable and configure interrupts on a pin already configured as
+            // input with 9
+            10 => self.configure_interrupt(pin_num, config),
+
+            // default
+            _ => ReturnCode::ENOSUPPORT
+        }
+    }
+
+    fn app_exited(&self, appid: AppId) {
+        // Pins stay configured, but their interrupts no longer reach the app
+        let owned = self.callback.get().map_or(false, |cb| {
+            cb.app_id() == appid
+        });
+        if owned {
+            self.callback.set(None);
         }
     }
 }
@@ -0,0 +1,286 @@
+//! Lets apps offer services to each other.
+//!
+//! A service app `allow`s a buffer holding its name with minor number 0 and
+//! registers that name with command 0. A client app puts the same name in its
+//! own name buffer and looks the service up with command 1, which returns the
+//! service's id. The client can then share a buffer with the service by
+//! `allow`ing it with minor number `id + 1`, and notify the service with
+//! command 2, passing the id as the argument.
+//!
+//! Notifying a service calls the callback it subscribed with minor number 0 as
+//! `callback(client_id, buffer_len, buffer_address)`. The service can read and
+//! write the client's buffer from then on, until the client shares another
+//! one. A service notifies a client back with command 3, passing the client's
+//! id, which calls the callback the client subscribed with minor number 1 as
+//! `callback(service_id, 0, 0)`.
+//!
+//! Shared buffers are mapped into the service's memory by the MPU, so they
+//! have to line up with an MPU region: a power of two of at least 32 bytes,
+//! aligned to its size, or a run of whole eighths of such a region.
+
+use hil::{Driver, ReturnCode};
+use process::{AppId, AppSlice, Callback, Container, MemoryRegion, Shared};
+use process::NUM_PROCS;
+
+/// Longest service name, in bytes.
+pub const MAX_NAME_LEN: usize = 32;
+
+pub struct App {
+    name_buffer: Option<AppSlice<Shared, u8>>,
+    /// The name the app registered as a service, `service_name_len` bytes
+    /// long. Apps that aren't services have a length of 0.
+    service_name: [u8; MAX_NAME_LEN],
+    service_name_len: usize,
+    service_callback: Option<Callback>,
+    client_callback: Option<Callback>,
+    /// Buffers shared with services, indexed by service id, along with the
+    /// service they were shared with.
+    shared: [Option<(AppId, AppSlice<Shared, u8>)>; NUM_PROCS]
+}
+
+impl Default for App {
+    fn default() -> App {
+        App {
+            name_buffer: None,
+            service_name: [0; MAX_NAME_LEN],
+            service_name_len: 0,
+            service_callback: None,
+            client_callback: None,
+            shared: [None, None, None, None]
+        }
+    }
+}
+
+pub struct IPC {
+    apps: Container<App>
+}
+
+impl IPC {
+    pub const fn new(container: Container<App>) -> IPC {
+        IPC {
+            apps: container
+        }
+    }
+
+    /// MPU regions covering the buffers clients have shared with `appid`,
+    /// indexed by client id. Buffers that no longer lie in their client's
+    /// own memory are left out, so the service never gets access to kernel
+    /// memory.
+    pub fn shared_regions(&self, appid: AppId) -> [Option<MemoryRegion>; NUM_PROCS] {
+        let mut regions = [None; NUM_PROCS];
+        for cntr in self.apps.iter() {
+            let _ = cntr.enter(|app, _| {
+                let client = app.appid().idx();
+                app.shared.get(appid.idx()).and_then(|s| s.as_ref())
+                          .map(|&(service, ref slice)| {
+                    if service == appid && slice.len() > 0 &&
+                            slice.is_app_memory() {
+                        let start = slice.as_ref().as_ptr() as usize;
+                        regions[client] = Some(
+                            MemoryRegion::covering(start, start + slice.len()));
+                    }
+                });
+            });
+        }
+        regions
+    }
+
+    /// The app with id `idx`, if it has used IPC. Only apps that registered a
+    /// name count if `service` is set.
+    fn app_with_id(&self, idx: usize, service: bool) -> Option<AppId> {
+        self.apps.iter().filter_map(|cntr| {
+            cntr.enter(|app, _| {
+                let appid = app.appid();
+                if appid.idx() == idx && (!service || app.service_name_len > 0) {
+                    Some(appid)
+                } else {
+                    None
+                }
+            }).unwrap_or(None)
+        }).next()
+    }
+
+    /// The service registered as `name`.
+    fn find_service(&self, name: &[u8]) -> Option<AppId> {
+        self.apps.iter().filter_map(|cntr| {
+            cntr.enter(|app, _| {
+                let len = app.service_name_len;
+                if len > 0 && &app.service_name[..len] == name {
+                    Some(app.appid())
+                } else {
+                    None
+                }
+            }).unwrap_or(None)
+        }).next()
+    }
+
+    /// Copies the contents of `appid`'s name buffer into `name`, returning its
+    /// length, or `None` if it has no usable name buffer.
+    fn read_name(&self, appid: AppId, name: &mut [u8; MAX_NAME_LEN])
+            -> Option<usize> {
+        self.apps.enter(appid, |app, _| {
+            app.name_buffer.as_ref().and_then(|buffer| {
+                let len = buffer.len();
+                if len == 0 || len > MAX_NAME_LEN {
+                    return None;
+                }
+                for (i, c) in buffer.as_ref().iter().enumerate() {
+                    name[i] = *c;
+                }
+                Some(len)
+            })
+        }).unwrap_or(None)
+    }
+
+    fn register(&self, appid: AppId) -> ReturnCode {
+        let mut name = [0; MAX_NAME_LEN];
+        let len = match self.read_name(appid, &mut name) {
+            Some(len) => len,
+            None => return ReturnCode::EINVAL
+        };
+        let taken = self.find_service(&name[..len]).map_or(false, |owner| {
+            owner != appid
+        });
+        if taken {
+            return ReturnCode::EBUSY;
+        }
+        self.apps.enter(appid, |app, _| {
+            app.service_name = name;
+            app.service_name_len = len;
+            ReturnCode::SuccessWithValue { value: appid.idx() }
+        }).unwrap_or_else(|err| err.into())
+    }
+
+    fn discover(&self, appid: AppId) -> ReturnCode {
+        let mut name = [0; MAX_NAME_LEN];
+        self.read_name(appid, &mut name).and_then(|len| {
+            self.find_service(&name[..len])
+        }).map_or(ReturnCode::FAIL, |service| {
+            ReturnCode::SuccessWithValue { value: service.idx() }
+        })
+    }
+
+    fn notify_service(&self, service_id: usize, client: AppId) -> ReturnCode {
+        let service = match self.app_with_id(service_id, true) {
+            Some(service) => service,
+            None => return ReturnCode::EINVAL
+        };
+        // Tell the service where the buffer shared with it is, if any
+        let (len, addr) = self.apps.enter(client, |app, _| {
+            match app.shared[service_id] {
+                Some((shared_with, ref slice)) if shared_with == service => {
+                    (slice.len(), slice.as_ref().as_ptr() as usize)
+                },
+                _ => (0, 0)
+            }
+        }).unwrap_or((0, 0));
+        self.apps.enter(service, |app, _| {
+            app.service_callback.map_or(ReturnCode::EOFF, |mut cb| {
+                if cb.schedule(client.idx(), len, addr) {
+                    ReturnCode::SUCCESS
+                } else {
+                    ReturnCode::EBUSY
+                }
+            })
+        }).unwrap_or_else(|err| err.into())
+    }
+
+    fn notify_client(&self, client_id: usize, service: AppId) -> ReturnCode {
+        let client = match self.app_with_id(client_id, false) {
+            Some(client) => client,
+            None => return ReturnCode::EINVAL
+        };
+        self.apps.enter(client, |app, _| {
+            app.client_callback.map_or(ReturnCode::EOFF, |mut cb| {
+                if cb.schedule(service.idx(), 0, 0) {
+                    ReturnCode::SUCCESS
+                } else {
+                    ReturnCode::EBUSY
+                }
+            })
+        }).unwrap_or_else(|err| err.into())
+    }
+}
+
+impl Driver for IPC {
+    fn subscribe(&self, subscribe_num: usize, callback: Callback)
+            -> ReturnCode {
+        self.apps.enter(callback.app_id(), |app, _| {
+            match subscribe_num {
+                0 => app.service_callback = Some(callback),
+                1 => app.client_callback = Some(callback),
+                _ => return ReturnCode::ENOSUPPORT
+            }
+            ReturnCode::SUCCESS
+        }).unwrap_or_else(|err| err.into())
+    }
+
+    fn command(&self, command_num: usize, id: usize, appid: AppId)
+            -> ReturnCode {
+        match command_num {
+            0 => self.register(appid),
+            1 => self.discover(appid),
+            2 => self.notify_service(id, appid),
+            3 => self.notify_client(id, appid),
+            _ => ReturnCode::ENOSUPPORT
+        }
+    }
+
+    fn allow(&self, appid: AppId, allow_num: usize,
+             slice: AppSlice<Shared, u8>) -> ReturnCode {
+        if allow_num == 0 {
+            return self.apps.enter(appid, |app, _| {
+                app.name_buffer = Some(slice);
+                ReturnCode::SUCCESS
+            }).unwrap_or_else(|err| err.into());
+        }
+
+        let service_id = allow_num - 1;
+        if service_id >= NUM_PROCS {
+            return ReturnCode::ENOSUPPORT;
+        }
+        let service = match self.app_with_id(service_id, true) {
+            Some(service) => service,
+            None => return ReturnCode::EINVAL
+        };
+        // The service only gets access to exactly the shared buffer
+        let start = slice.as_ref().as_ptr() as usize;
+        let end = start + slice.len();
+        if start == end ||
+                MemoryRegion::covering(start, end).effective_range() != (start, end) {
+            return ReturnCode::ESIZE;
+        }
+        self.apps.enter(appid, |app, _| {
+            app.shared[service_id] = Some((service, slice));
+            ReturnCode::SUCCESS
+        }).unwrap_or_else(|err| err.into())
+    }
+
+    fn app_exited(&self, appid: AppId) {
+        // Stop offering the app's service and forget what it shared, so
+        // neither the app nor its buffers can be reached until it runs again
+        let _ = self.apps.enter(appid, |app, _| {
+            app.name_buffer = None;
+            app.service_name_len = 0;
+            app.service_callback = None;
+            app.client_callback = None;
+            app.shared = [None, None, None, None];
+        });
+        // Buffers clients shared with the app's service are no longer mapped
+        // for it
+        for cntr in self.apps.iter() {
+            let _ = cntr.enter(|app, _| {
+                let shared_with_app = app.shared.get(appid.idx())
+                    .and_then(|s| s.as_ref())
+                    .map_or(false, |&(service, _)| service == appid);
+                if shared_with_app {
+                    app.shared[appid.idx()] = None;
+                }
+            });
+        }
+    }
+
+    fn grant_bytes(&self, appid: AppId) -> usize {
+        self.apps.grant_bytes(appid)
+    }
+}
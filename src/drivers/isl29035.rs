@@ -1,7 +1,7 @@
 //! Driver for the ISL29035 digital light sensor
 
 use process::{AppId, Callback};
-use hil::Driver;
+use hil::{Driver, ReturnCode};
 use hil::i2c::{I2CDevice, I2CClient, Error};
 use core::cell::Cell;
 use common::take_cell::TakeCell;
@@ -59,23 +59,35 @@ impl<'a> Isl29035<'a> {
 }
 
 impl<'a> Driver for Isl29035<'a> {
-    fn subscribe(&self, subscribe_num: usize, callback: Callback) -> isize {
+    fn subscribe(&self, subscribe_num: usize, callback: Callback)
+            -> ReturnCode {
         match subscribe_num {
             0 => {
                 self.callback.set(Some(callback));
-                0
+                ReturnCode::SUCCESS
             }
-            _ => -1
+            _ => ReturnCode::ENOSUPPORT
         }
     }
 
-    fn command(&self, command_num: usize, _arg1: usize, _: AppId) -> isize {
+    fn command(&self, command_num: usize, _arg1: usize, _: AppId)
+            -> ReturnCode {
         match command_num {
             0 => {
                 self.start_read_lux();
-                0
+                ReturnCode::SUCCESS
             },
-            _ => -1
+            _ => ReturnCode::ENOSUPPORT
+        }
+    }
+
+    fn app_exited(&self, appid: AppId) {
+        // A reading in progress still finishes, but nobody is told the result
+        let owned = self.callback.get().map_or(false, |cb| {
+            cb.app_id() == appid
+        });
+        if owned {
+            self.callback.set(None);
         }
     }
 }
@@ -9,8 +9,10 @@ extern crate process;
 
 pub mod console;
 pub mod gpio;
+pub mod ipc;
 pub mod isl29035;
 pub mod nrf51822_serialization;
+pub mod process_info;
 pub mod timer;
 pub mod tmp006;
 pub mod spi;
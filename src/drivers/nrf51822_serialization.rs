@@ -1,6 +1,6 @@
 use common::take_cell::TakeCell;
 use process::{AppId, Callback, AppSlice, Shared, NUM_PROCS};
-use hil::Driver;
+use hil::{Driver, ReturnCode};
 use hil::uart::{UART, Client};
 
 ///
@@ -32,7 +32,8 @@ impl<'a, U: UART> Nrf51822Serialization<'a, U> {
     pub const fn new(uart: &'a U, buffer: &'static mut [u8]) -> Nrf51822Serialization<'a, U> {
         Nrf51822Serialization {
             uart: uart,
-            apps: [TakeCell::empty(), TakeCell::empty()],
+            apps: [TakeCell::empty(), TakeCell::empty(),
+                   TakeCell::empty(), TakeCell::empty()],
             buffer: TakeCell::new(buffer)
         }
     }
@@ -53,7 +54,7 @@ impl<'a, U: UART> Driver for Nrf51822Serialization<'a, U> {
     fn allow(&self,
              appid: AppId,
              allow_type: usize,
-             slice: AppSlice<Shared, u8>) -> isize {
+             slice: AppSlice<Shared, u8>) -> ReturnCode {
         let app = appid.idx();
         match allow_type {
             0 => {
@@ -73,7 +74,7 @@ impl<'a, U: UART> Driver for Nrf51822Serialization<'a, U> {
                     }
                 };
                 self.apps[app].replace(resapp);
-                0
+                ReturnCode::SUCCESS
             },
             1 => {
                 let resapp = match self.apps[app].take() {
@@ -90,9 +91,9 @@ impl<'a, U: UART> Driver for Nrf51822Serialization<'a, U> {
                     }
                 };
                 self.apps[app].replace(resapp);
-                0
+                ReturnCode::SUCCESS
             },
-            _ => -1
+            _ => ReturnCode::ENOSUPPORT
         }
     }
 
@@ -104,7 +105,8 @@ impl<'a, U: UART> Driver for Nrf51822Serialization<'a, U> {
     /// subscribe_type: 0 - add the callback
     ///
     #[inline(never)]
-    fn subscribe(&self, subscribe_type: usize, callback: Callback) -> isize {
+    fn subscribe(&self, subscribe_type: usize, callback: Callback)
+            -> ReturnCode {
         let app = callback.app_id().idx();
         match subscribe_type {
             0 => {
@@ -122,9 +124,9 @@ impl<'a, U: UART> Driver for Nrf51822Serialization<'a, U> {
                     }
                 };
                 self.apps[app].replace(resapp);
-                0
+                ReturnCode::SUCCESS
             },
-            _ => -1
+            _ => ReturnCode::ENOSUPPORT
         }
     }
 
@@ -132,7 +134,7 @@ impl<'a, U: UART> Driver for Nrf51822Serialization<'a, U> {
     ///
     /// command_type: 0 - Write a byte to the UART.
     ///
-    fn command(&self, command_type: usize, _: usize, _: AppId) -> isize {
+    fn command(&self, command_type: usize, _: usize, _: AppId) -> ReturnCode {
 
         match command_type {
             0 => {
@@ -150,16 +152,22 @@ impl<'a, U: UART> Driver for Nrf51822Serialization<'a, U> {
                                 }
                                 self.uart.send_bytes(buffer, write_len);
                             });
-                            0
+                            ReturnCode::SUCCESS
                         },
-                        None => -2
+                        None => ReturnCode::EINVAL
                     }
                 });
-                result.unwrap_or(-1)
+                result.unwrap_or(ReturnCode::FAIL)
             },
-            _ => -1
+            _ => ReturnCode::ENOSUPPORT
         }
     }
+
+    fn app_exited(&self, appid: AppId) {
+        // A write in progress still finishes and gives the kernel buffer
+        // back in `write_done`
+        self.apps.get(appid.idx()).map(|app| app.take());
+    }
 }
 
 fn each_some<'a, T, I, F>(lst: I, mut f: F)
@@ -0,0 +1,97 @@
+//! Lets apps read the kernel's per-process counters.
+//!
+//! Command 0 returns the number of process slots. Command 1, given a process
+//! index, writes that process's counters as 32-bit little-endian words into
+//! the buffer `allow`ed with minor number 0, and returns the number of words
+//! written. The words are, in order: timeslices run, timeslices that expired,
+//! WAIT, SUBSCRIBE, COMMAND, ALLOW, MEMOP and EXIT system calls made (in SVC
+//! number order), callbacks queued and callbacks dropped because the process's
+//! queue was full.
+
+use hil::{Driver, ReturnCode};
+use process::{self, AppId, AppSlice, Container, Shared, NUM_PROCS};
+
+pub struct App {
+    buffer: Option<AppSlice<Shared, u8>>
+}
+
+impl Default for App {
+    fn default() -> App {
+        App {
+            buffer: None
+        }
+    }
+}
+
+pub struct ProcessInfo {
+    apps: Container<App>
+}
+
+impl ProcessInfo {
+    pub const fn new(container: Container<App>) -> ProcessInfo {
+        ProcessInfo {
+            apps: container
+        }
+    }
+
+    fn write_statistics(&self, appid: AppId, idx: usize) -> ReturnCode {
+        let stats = match process::process::statistics(idx) {
+            Some(stats) => stats,
+            None => return ReturnCode::EINVAL
+        };
+        let words = [
+            stats.timeslices,
+            stats.timeslice_expirations,
+            stats.syscalls[0],
+            stats.syscalls[1],
+            stats.syscalls[2],
+            stats.syscalls[3],
+            stats.syscalls[4],
+            stats.syscalls[5],
+            stats.callbacks_queued,
+            stats.callbacks_dropped
+        ];
+        self.apps.enter(appid, |app, _| {
+            app.buffer.as_mut().map_or(ReturnCode::ESIZE, |buffer| {
+                let buffer = buffer.as_mut();
+                let mut written = 0;
+                for (chunk, word) in buffer.chunks_mut(4).zip(words.iter()) {
+                    if chunk.len() < 4 {
+                        break;
+                    }
+                    for (i, byte) in chunk.iter_mut().enumerate() {
+                        *byte = (*word >> (8 * i)) as u8;
+                    }
+                    written += 1;
+                }
+                ReturnCode::SuccessWithValue { value: written }
+            })
+        }).unwrap_or_else(|err| err.into())
+    }
+}
+
+impl Driver for ProcessInfo {
+    fn command(&self, command_num: usize, idx: usize, appid: AppId)
+            -> ReturnCode {
+        match command_num {
+            0 => ReturnCode::SuccessWithValue { value: NUM_PROCS },
+            1 => self.write_statistics(appid, idx),
+            _ => ReturnCode::ENOSUPPORT
+        }
+    }
+
+    fn allow(&self, appid: AppId, allow_num: usize,
+             slice: AppSlice<Shared, u8>) -> ReturnCode {
+        match allow_num {
+            0 => self.apps.enter(appid, |app, _| {
+                app.buffer = Some(slice);
+                ReturnCode::SUCCESS
+            }).unwrap_or_else(|err| err.into()),
+            _ => ReturnCode::ENOSUPPORT
+        }
+    }
+
+    fn grant_bytes(&self, appid: AppId) -> usize {
+        self.apps.grant_bytes(appid)
+    }
+}
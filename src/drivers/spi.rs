@@ -1,7 +1,7 @@
 use common::take_cell::TakeCell;
 use core::cell::Cell;
 use process::{AppId,Callback,AppSlice,Shared,NUM_PROCS};
-use hil::Driver;
+use hil::{Driver, ReturnCode};
 use hil::spi_master::{SpiMaster,SpiCallback};
 use core::cmp;
 use hil::spi_master::ClockPolarity;
@@ -39,7 +39,8 @@ impl<'a, S: SpiMaster> Spi<'a, S> {
         Spi {
             spi_master: spi_master,
             busy: Cell::new(false),
-            apps: [TakeCell::empty(), TakeCell::empty()],
+            apps: [TakeCell::empty(), TakeCell::empty(),
+                   TakeCell::empty(), TakeCell::empty()],
             kernel_len: Cell::new(0),
             kernel_read : TakeCell::empty(),
             kernel_write : TakeCell::empty()
@@ -78,7 +79,7 @@ impl<'a, S: SpiMaster> Spi<'a, S> {
 
 impl<'a, S: SpiMaster> Driver for Spi<'a, S> {
     fn allow(&self, appid: AppId,
-             allow_num: usize, slice: AppSlice<Shared, u8>) -> isize {
+             allow_num: usize, slice: AppSlice<Shared, u8>) -> ReturnCode {
         let app = appid.idx();
         match allow_num {
             0 => {
@@ -96,7 +97,7 @@ impl<'a, S: SpiMaster> Driver for Spi<'a, S> {
                     }
                 };
                 self.apps[app].replace(appc);
-                0
+                ReturnCode::SUCCESS
             },
             1 => {
                 let appc = match self.apps[app].take() {
@@ -113,14 +114,15 @@ impl<'a, S: SpiMaster> Driver for Spi<'a, S> {
                     }
                 };
                 self.apps[app].replace(appc);
-                0
+                ReturnCode::SUCCESS
             }
-            _ => -1
+            _ => ReturnCode::ENOSUPPORT
         }
     }
 
     #[inline(never)]
-    fn subscribe(&self, subscribe_num: usize, callback: Callback) -> isize {
+    fn subscribe(&self, subscribe_num: usize, callback: Callback)
+            -> ReturnCode {
         match subscribe_num {
             0 /* read_write */ => {
                 let appc = match self.apps[0].take() {
@@ -137,11 +139,31 @@ impl<'a, S: SpiMaster> Driver for Spi<'a, S> {
                     }
                 };
                 self.apps[0].replace(appc);
-                0
+                ReturnCode::SUCCESS
             },
-            _ => -1
+            _ => ReturnCode::ENOSUPPORT
         }
     }
+
+    fn app_exited(&self, appid: AppId) {
+        // Transfers use the buffers and callback in the first slot, so
+        // clearing it cancels any transfer the app started. The kernel
+        // buffers come back in `read_write_done`.
+        if appid.idx() == 0 {
+            self.apps[0].take();
+        } else {
+            self.apps.get(appid.idx()).map(|app| app.take());
+            self.apps[0].map(|app| {
+                let owned = app.callback.map_or(false, |cb| {
+                    cb.app_id() == appid
+                });
+                if owned {
+                    app.callback = None;
+                }
+            });
+        }
+    }
+
     /*
      * 0: read/write a single byte (blocking)
      * 1: read/write buffers
@@ -186,16 +208,17 @@ impl<'a, S: SpiMaster> Driver for Spi<'a, S> {
      *   - does nothing if lock not held
      */
 
-    fn command(&self, cmd_num: usize, arg1: usize, _: AppId) -> isize {
+    fn command(&self, cmd_num: usize, arg1: usize, _: AppId) -> ReturnCode {
         match cmd_num {
             0 /* read_write_byte */ => { 
-                self.spi_master.read_write_byte(arg1 as u8) as isize
+                let value = self.spi_master.read_write_byte(arg1 as u8);
+                ReturnCode::SuccessWithValue { value: value as usize }
             },
             1 /* read_write_bytes */ => { 
                 if self.busy.get() {
-                    return -1;
+                    return ReturnCode::EBUSY;
                 }
-                let mut result = -1;
+                let mut result = ReturnCode::ESIZE;
                 self.apps[0].map(|app| {
                     let mut mlen = 0;
                     // If write buffer too small, return
@@ -210,7 +233,7 @@ impl<'a, S: SpiMaster> Driver for Spi<'a, S> {
                         app.index = 0;
                         self.busy.set(true);
                         self.do_next_read_write(app);
-                        result = 0;
+                        result = ReturnCode::SUCCESS;
                     }
                 });
                 return result;
@@ -219,49 +242,54 @@ impl<'a, S: SpiMaster> Driver for Spi<'a, S> {
                 let cs = arg1 as u8;
                 if cs <= 3 {
                     self.spi_master.set_chip_select(cs);
-                    0
+                    ReturnCode::SUCCESS
                 } else {
-                    -1
+                    ReturnCode::EINVAL
                 }
             }
             3 /* get chip select */ => {
-                self.spi_master.get_chip_select() as isize
+                let value = self.spi_master.get_chip_select();
+                ReturnCode::SuccessWithValue { value: value as usize }
             }
             4 /* set baud rate */ => {
-                self.spi_master.set_rate(arg1 as u32) as isize
+                let value = self.spi_master.set_rate(arg1 as u32);
+                ReturnCode::SuccessWithValue { value: value as usize }
             }
             5 /* get baud rate */ => {
-                self.spi_master.get_rate() as isize
+                let value = self.spi_master.get_rate();
+                ReturnCode::SuccessWithValue { value: value as usize }
             }
             6 /* set phase */ => {
                 match arg1 {
                     0 => self.spi_master.set_phase(ClockPhase::SampleLeading),
                     _ => self.spi_master.set_phase(ClockPhase::SampleTrailing),
                 };
-                0
+                ReturnCode::SUCCESS
             }
             7 /* get phase */ => {
-                self.spi_master.get_phase() as isize
+                let value = self.spi_master.get_phase();
+                ReturnCode::SuccessWithValue { value: value as usize }
             }
             8 /* set polarity */ => {
                 match arg1 {
                     0 => self.spi_master.set_clock(ClockPolarity::IdleLow),
                     _ => self.spi_master.set_clock(ClockPolarity::IdleHigh),
                 };
-                0
+                ReturnCode::SUCCESS
             }
             9 /* get polarity */ => {
-                self.spi_master.get_clock() as isize
+                let value = self.spi_master.get_clock();
+                ReturnCode::SuccessWithValue { value: value as usize }
             }
             10 /* hold low */ => {
                 self.spi_master.hold_low();
-                0
+                ReturnCode::SUCCESS
             }
             11 /* release low */ => {
                 self.spi_master.release_low();
-                0
+                ReturnCode::SUCCESS
             }
-            _ => -1
+            _ => ReturnCode::ENOSUPPORT
         }
     }
 }
@@ -271,6 +299,13 @@ impl<'a, S: SpiMaster> SpiCallback for Spi<'a, S> {
                        writebuf: Option<&'static mut [u8]>,
                        readbuf:  Option<&'static mut [u8]>,
                        length: usize) {
+        if self.apps[0].is_none() {
+            // The app exited in the middle of the transfer
+            self.kernel_read.put(readbuf);
+            self.kernel_write.put(writebuf);
+            self.busy.set(false);
+            return;
+        }
         self.apps[0].map(|app| {
             if app.app_read.is_some() {
                 let src = readbuf.as_ref().unwrap();
@@ -1,9 +1,12 @@
 use core::cell::Cell;
 use process::{AppId, Container, Callback};
-use hil::Driver;
+use hil::{Driver, ReturnCode};
 use hil::alarm::{Alarm, AlarmClient, Frequency};
 use hil::timer::{Timer};
 
+/// Flag for command 3 to fire the timer every interval rather than once.
+pub const FLAG_REPEATING: usize = 1 << 0;
+
 #[derive(Copy, Clone)]
 pub struct TimerData {
     t0: u32,
@@ -48,7 +51,7 @@ impl<'a, A: Alarm + 'a> TimerDriver<'a, A> {
                         next_dist = t_dist;
                     }
                 }
-            });
+            }).unwrap_or(());
         }
         if next_alarm != u32::max_value() {
             self.alarm.set_alarm(next_alarm);
@@ -57,25 +60,27 @@ impl<'a, A: Alarm + 'a> TimerDriver<'a, A> {
 }
 
 impl<'a, A: Alarm> Driver for TimerDriver<'a, A> {
-    fn subscribe(&self, _: usize, callback: Callback) -> isize {
+    fn subscribe(&self, _: usize, callback: Callback) -> ReturnCode {
         self.app_timer.enter(callback.app_id(), |td, _allocator| {
             td.callback = Some(callback);
-            0
-        }).unwrap_or(-1)
+            ReturnCode::SUCCESS
+        }).unwrap_or_else(|err| err.into())
     }
 
-    fn command(&self, cmd_type: usize, interval: usize, caller_id: AppId)
-            -> isize {
+    /// Commands 0 and 1 start a oneshot and a repeating timer, `interval`
+    /// milliseconds long. Command 3 does the same with the kind of timer
+    /// chosen by `flags`, and command 2 stops the timer.
+    fn command2(&self, cmd_type: usize, interval: usize, flags: usize,
+                caller_id: AppId) -> ReturnCode {
         // First, convert from milliseconds to native clock frequency
         let interval = (interval as u32) * <A::Frequency>::frequency() / 1000;
 
-        // Returns the error code to return to the user (0 for success, negative
-        // otherwise) and whether we need to reset which is the next active
-        // alarm. We only _don't_ reset if we're disabling the underlying alarm
-        // anyway, if the underlying alarm is currently disabled and we're
-        // enabling the first alarm, or on an error (i.e. no change to the
-        // alarms).
-        let (err_code, reset) = self.app_timer.enter(caller_id, |td, _alloc| {
+        // Returns the code to return to the user and whether we need to reset
+        // which is the next active alarm. We only _don't_ reset if we're
+        // disabling the underlying alarm anyway, if the underlying alarm is
+        // currently disabled and we're enabling the first alarm, or on an
+        // error (i.e. no change to the alarms).
+        let (return_code, reset) = self.app_timer.enter(caller_id, |td, _alloc| {
             match cmd_type {
                 2 /* Stop */ => {
                     if td.interval > 0 {
@@ -85,18 +90,18 @@ impl<'a, A: Alarm> Driver for TimerDriver<'a, A> {
                         self.num_armed.set(num_armed - 1);
                         if num_armed == 1 {
                             self.alarm.disable_alarm();
-                            (0, false)
+                            (ReturnCode::SUCCESS, false)
                         } else {
-                            (0, true)
+                            (ReturnCode::SUCCESS, true)
                         }
                     } else {
-                        (-2, false)
+                        (ReturnCode::EALREADY, false)
                     }
                 },
-                /* 0 for Oneshot, 1 for Repeat */
-                cmd_type if cmd_type <= 1 => {
+                /* 0 for Oneshot, 1 for Repeat, 3 for either by flags */
+                0 | 1 | 3 => {
                     if interval == 0 {
-                        return (-2, false);
+                        return (ReturnCode::EINVAL, false);
                     }
 
                     // if previously unarmed, but now will become armed
@@ -107,22 +112,50 @@ impl<'a, A: Alarm> Driver for TimerDriver<'a, A> {
                     td.t0 = self.alarm.now();
                     td.interval = interval;
 
-                    // Repeat if cmd_type was 1
-                    td.repeating = cmd_type == 1;
+                    td.repeating = match cmd_type {
+                        0 => false,
+                        1 => true,
+                        _ => flags & FLAG_REPEATING != 0
+                    };
                     if self.alarm.is_armed() {
-                        (0, true)
+                        (ReturnCode::SUCCESS, true)
                     } else {
                         self.alarm.set_alarm(td.t0.wrapping_add(td.interval));
-                        (0, false)
+                        (ReturnCode::SUCCESS, false)
                     }
                 },
-                _ => (-1, false)
+                _ => (ReturnCode::ENOSUPPORT, false)
             }
-        }).unwrap_or((-3, false));
+        }).unwrap_or_else(|err| (err.into(), false));
         if reset {
             self.reset_active_timer();
         }
-        err_code
+        return_code
+    }
+
+    fn app_exited(&self, appid: AppId) {
+        // Apps that never used a timer have no container to look in
+        let armed = self.app_timer.container(appid).map_or(false, |timer| {
+            timer.enter(|td, _| {
+                let armed = td.interval > 0;
+                td.interval = 0;
+                td.callback = None;
+                armed
+            }).unwrap_or(false)
+        });
+        if armed {
+            let num_armed = self.num_armed.get() - 1;
+            self.num_armed.set(num_armed);
+            if num_armed == 0 {
+                self.alarm.disable_alarm();
+            } else {
+                self.reset_active_timer();
+            }
+        }
+    }
+
+    fn grant_bytes(&self, appid: AppId) -> usize {
+        self.app_timer.grant_bytes(appid)
     }
 }
 
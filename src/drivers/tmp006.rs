@@ -2,15 +2,12 @@ use core::cell::Cell;
 use common::take_cell::TakeCell;
 use common::math::{sqrtf32, get_errno};
 use process::{AppId, Callback};
-use hil::Driver;
+use hil::{Driver, ReturnCode};
 use hil::i2c;
 use hil::gpio::{GPIOPin, InputMode, InterruptMode, Client};
 
 pub static mut BUFFER : [u8; 3] = [0; 3];
 
-// error codes for this driver
-const ERR_BAD_VALUE: isize = -2;
-
 const MAX_SAMPLING_RATE: u8 = 0x0;
 const DEFAULT_SAMPLING_RATE: u8 = 0x02;
 
@@ -259,7 +256,8 @@ impl<'a> Client for TMP006<'a> {
 }
 
 impl<'a> Driver for TMP006<'a> {
-    fn subscribe(&self, subscribe_num: usize, callback: Callback) -> isize {
+    fn subscribe(&self, subscribe_num: usize, callback: Callback)
+            -> ReturnCode {
         match subscribe_num {
             // single temperature reading with callback
             0 => {
@@ -273,7 +271,7 @@ impl<'a> Driver for TMP006<'a> {
                 //  turn up the sampling rate so we get the sample faster
                 self.enable_sensor(MAX_SAMPLING_RATE);
 
-                0
+                ReturnCode::SUCCESS
             },
 
             // periodic temperature reading subscription
@@ -287,27 +285,27 @@ impl<'a> Driver for TMP006<'a> {
                 // enable temperature sensor
                 self.enable_sensor(self.sampling_period.get());
 
-                0
+                ReturnCode::SUCCESS
             },
 
             // default
-            _ => -1
+            _ => ReturnCode::ENOSUPPORT
         }
     }
 
-    fn command(&self, command_num: usize, data: usize, _: AppId) -> isize {
+    fn command(&self, command_num: usize, data: usize, _: AppId) -> ReturnCode {
         match command_num {
             // set period for sensing
             0 => {
                 // bounds check on the period
                 if (data & 0xFFFFFFF8) != 0 {
-                    return ERR_BAD_VALUE;
+                    return ReturnCode::EINVAL;
                 }
 
                 // set period value
                 self.sampling_period.set((data & 0x7) as u8);
 
-                0
+                ReturnCode::SUCCESS
             },
 
             // unsubscribe callback
@@ -318,11 +316,25 @@ impl<'a> Driver for TMP006<'a> {
                 // disable temperature sensor
                 self.disable_sensor(None);
 
-                0
+                ReturnCode::SUCCESS
             },
 
             // default
-            _ => -1
+            _ => ReturnCode::ENOSUPPORT
+        }
+    }
+
+    fn app_exited(&self, appid: AppId) {
+        let owned = self.callback.get().map_or(false, |cb| {
+            cb.app_id() == appid
+        });
+        if owned {
+            // Nobody else is subscribed, so stop sampling, like unsubscribing.
+            // If the sensor is busy the buffer isn't back yet, and the
+            // reading in progress disables it when it finishes instead.
+            self.callback.set(None);
+            self.repeated_mode.set(false);
+            self.disable_sensor(None);
         }
     }
 }
@@ -31,6 +31,88 @@
 //! it's function and it interacts with `subscribe`.
 
 use process::{AppId, AppSlice, Callback, Shared};
+use process::process::Error;
+
+/// The result of a `subscribe`, `command` or `allow`, and of the `memop`
+/// system call.
+///
+/// Apps receive it in `r0`, encoded as an `isize`: `SuccessWithValue` as its
+/// value, which must fit in a non-negative `isize`, and the rest as fixed
+/// numbers. Success is never negative, and errors always are, so apps can tell
+/// them apart without knowing the driver. `apps/libs/tock.h` defines the same
+/// numbers as `TOCK_*`, and the two must be kept in sync.
+#[derive(Copy,Clone,Debug,PartialEq,Eq)]
+pub enum ReturnCode {
+    /// Success, returning a value to the app, e.g. a reading or an id.
+    SuccessWithValue { value: usize },
+    /// Success (0).
+    SUCCESS,
+    /// Generic failure (-1).
+    FAIL,
+    /// The driver or resource is busy with another request (-2).
+    EBUSY,
+    /// The requested state is already in effect (-3).
+    EALREADY,
+    /// The device is off or not listening (-4).
+    EOFF,
+    /// The request touches memory or a resource the kernel reserves (-5).
+    ERESERVE,
+    /// An argument is invalid (-6).
+    EINVAL,
+    /// A buffer or length is the wrong size (-7).
+    ESIZE,
+    /// The operation was cancelled (-8).
+    ECANCEL,
+    /// Out of memory (-9).
+    ENOMEM,
+    /// The driver doesn't support the minor number or operation (-10).
+    ENOSUPPORT,
+    /// There is no driver with the requested major number (-11).
+    ENODEVICE,
+    /// The device isn't installed (-12).
+    EUNINSTALLED,
+    /// A packet was sent but not acknowledged (-13).
+    ENOACK,
+    /// The app isn't allowed to use the driver (-14).
+    EPERM
+}
+
+impl From<ReturnCode> for isize {
+    fn from(code: ReturnCode) -> isize {
+        match code {
+            ReturnCode::SuccessWithValue { value } => value as isize,
+            ReturnCode::SUCCESS => 0,
+            ReturnCode::FAIL => -1,
+            ReturnCode::EBUSY => -2,
+            ReturnCode::EALREADY => -3,
+            ReturnCode::EOFF => -4,
+            ReturnCode::ERESERVE => -5,
+            ReturnCode::EINVAL => -6,
+            ReturnCode::ESIZE => -7,
+            ReturnCode::ECANCEL => -8,
+            ReturnCode::ENOMEM => -9,
+            ReturnCode::ENOSUPPORT => -10,
+            ReturnCode::ENODEVICE => -11,
+            ReturnCode::EUNINSTALLED => -12,
+            ReturnCode::ENOACK => -13,
+            ReturnCode::EPERM => -14
+        }
+    }
+}
+
+/// How errors from checking app memory or allocating grants reach the app.
+impl From<Error> for ReturnCode {
+    fn from(err: Error) -> ReturnCode {
+        match err {
+            Error::NoSuchApp => ReturnCode::FAIL,
+            Error::OutOfMemory => ReturnCode::ENOMEM,
+            Error::AddressOutOfBounds => ReturnCode::EINVAL,
+            Error::KernelMemory => ReturnCode::ERESERVE,
+            Error::AddressOverflow => ReturnCode::ESIZE,
+            Error::InUse => ReturnCode::EBUSY
+        }
+    }
+}
 
 /// `Driver`s implement the three driver-specific system calls: `subscribe`,
 /// `command` and `allow`.
@@ -56,28 +138,39 @@ pub trait Driver {
     /// application, and the application is responsible for virtualizing that
     /// timer if it needs to.
     ///
-    /// The driver should signal success or failure through the sign of the
-    /// return value from `subscribe`. A negative return value signifies an
-    /// error, while positive a return values signifies success. In addition,
-    /// the magnitude of the return value of can signify extra information such
-    /// as error type.
+    /// The driver signals success or the kind of failure through the
+    /// `ReturnCode` it returns.
     #[allow(unused_variables)]
-    fn subscribe(&self, minor_num: usize, callback: Callback) -> isize {
-        -1
+    fn subscribe(&self, minor_num: usize, callback: Callback) -> ReturnCode {
+        ReturnCode::ENOSUPPORT
     }
 
     /// `command` instructs a driver to perform some action synchronously.
     ///
     /// The return value should reflect the result of an action. For example,
-    /// enabling/disabling a peripheral should return a success or error code.
-    /// Reading the current system time should return the time as an integer.
+    /// enabling/disabling a peripheral should return `SUCCESS` or an error.
+    /// Reading the current system time should return the time as a
+    /// `SuccessWithValue`.
     ///
     /// Commands should not execute long running tasks synchronously. However,
     /// commands might "kick-off" asynchronous tasks in coordination with a
     /// `subscribe` call.
     #[allow(unused_variables)]
-    fn command(&self, minor_num: usize, r2: usize, caller_id: AppId) -> isize {
-        -1
+    fn command(&self, minor_num: usize, r2: usize, caller_id: AppId)
+            -> ReturnCode {
+        ReturnCode::ENOSUPPORT
+    }
+
+    /// `command2` is `command` with a second argument, passed in `r3`.
+    ///
+    /// The kernel calls this for every `command` system call. By default it
+    /// ignores `r3` and calls `command`, so drivers that take one argument
+    /// only implement `command`. Apps built before `r3` was passed leave it
+    /// undefined, so drivers should only read it for commands added since.
+    #[allow(unused_variables)]
+    fn command2(&self, minor_num: usize, r2: usize, r3: usize,
+                caller_id: AppId) -> ReturnCode {
+        self.command(minor_num, r2, caller_id)
     }
 
     /// `allow` lets an application give the driver access to a buffer in the
@@ -87,8 +180,26 @@ pub trait Driver {
     /// driver should not rely on the contents of the buffer to remain
     /// unchanged.
     #[allow(unused_variables)]
-    fn allow(&self, app: AppId, minor_num: usize, slice: AppSlice<Shared, u8>) -> isize {
-        -1
+    fn allow(&self, app: AppId, minor_num: usize, slice: AppSlice<Shared, u8>)
+            -> ReturnCode {
+        ReturnCode::ENOSUPPORT
+    }
+
+    /// Called when `app` faults, is terminated or restarts, before its grant
+    /// memory is released. Drivers cancel operations the app started, take
+    /// back kernel buffers lent out on its behalf and forget its callbacks
+    /// and buffers, so nothing refers to the app once it is gone. State kept
+    /// in the app's grants is released with them.
+    #[allow(unused_variables)]
+    fn app_exited(&self, app: AppId) {
+    }
+
+    /// The number of bytes of `app`'s grant memory the driver is using.
+    /// Drivers that keep per-application state in a `Container` report its
+    /// usage here so the kernel can show it.
+    #[allow(unused_variables)]
+    fn grant_bytes(&self, app: AppId) -> usize {
+        0
     }
 }
 
@@ -17,7 +17,7 @@ pub mod timer;
 pub mod uart;
 pub mod adc;
 
-pub use driver::Driver;
+pub use driver::{Driver, ReturnCode};
 
 pub trait Controller {
     type Config;
@@ -0,0 +1,83 @@
+//! Reports for processes that fault.
+//!
+//! A report names the app, decodes the fault status registers, says which of
+//! the app's MPU regions (as set in `sched::do_process`) the faulting address
+//! falls in, and dumps the stacked registers with the PC as an offset into the
+//! app's text so it can be symbolized with `arm-none-eabi-addr2line`. Apps
+//! the platform's syscall filter faults get a shorter report naming the
+//! system call.
+
+use platform::Platform;
+use process::{AppId, Process};
+
+/// Address the app's text is linked at (see `apps/*/loader.ld`).
+const APP_TEXT_LINK_ADDR: usize = 0x80000000;
+
+pub unsafe fn report(platform: &mut Platform, process: &Process, appid: AppId) {
+    let status = platform.fault_status();
+
+    print_title(process, appid);
+    print!("{}", status);
+
+    if !process.stack_in_bounds() {
+        print!("\tStack pointer 0x{:08x} is outside the process's stack \
+                0x{:08x}-0x{:08x}\r\n",
+               process.cur_stack() as usize,
+               process.stack_limit() as usize,
+               process.app_accessible_end() as usize);
+        return;
+    }
+
+    status.fault_address().map(|addr| print_region(process, addr as usize));
+
+    print!("\tr0  0x{:08x}\tr1  0x{:08x}\tr2  0x{:08x}\tr3  0x{:08x}\r\n",
+           process.r0(), process.r1(), process.r2(), process.r3());
+    print!("\tr12 0x{:08x}\tlr  0x{:08x}\tpc  0x{:08x}\txPSR 0x{:08x}\r\n",
+           process.r12(), process.lr(), process.pc(), process.xpsr());
+
+    match process.text_offset(process.pc()) {
+        Some(offset) => {
+            print!("\tpc is at text+0x{:x} (0x{:08x} in the app's ELF)\r\n",
+                   offset, APP_TEXT_LINK_ADDR + offset);
+        },
+        None => {
+            print!("\tpc is outside the app's text\r\n");
+        }
+    }
+}
+
+/// Reports a process faulted by the platform's syscall filter for the system
+/// call it just made.
+pub fn report_syscall_violation(process: &Process, appid: AppId) {
+    print_title(process, appid);
+    print!("\tSystem call {} to driver {}, minor number {}, denied by \
+            policy\r\n",
+           process.svc_number().unwrap_or(0), process.r0(), process.r1());
+}
+
+/// Names the app by its index and, if its header gave it one, its name.
+fn print_title(process: &Process, appid: AppId) {
+    match process.name() {
+        "" => print!("\r\n---| App {} faulted |---\r\n", appid.idx()),
+        name => print!("\r\n---| App {} ({}) faulted |---\r\n",
+                       appid.idx(), name)
+    }
+}
+
+fn print_region(process: &Process, addr: usize) {
+    let (data, text, guard) = process.memory_regions();
+    // Higher numbered regions take priority, so check them first
+    let regions = [(2, "stack guard, the stack overflowed", guard),
+                   (1, "text", text),
+                   (0, "data", data)];
+    for &(num, name, region) in regions.iter() {
+        if region.contains(addr) {
+            print!("\tFault address 0x{:08x} is in MPU region {} ({}): {}\r\n",
+                   addr, num, name, region);
+            return;
+        }
+    }
+    print!("\tFault address 0x{:08x} is outside the app's MPU regions\r\n",
+           addr);
+    print!("\t  data: {}\r\n\t  text: {}\r\n", data, text);
+}
@@ -6,20 +6,26 @@ extern crate common;
 extern crate support;
 extern crate hil;
 extern crate process;
+#[macro_use]
 extern crate platform;
 
+mod fault;
 mod sched;
+mod shell;
+mod trace;
 
 pub mod syscall;
 
 #[allow(improper_ctypes)]
 extern {
     static _sapps : usize;
+    static _eapps_flash : usize;
 }
 
 #[no_mangle]
 pub extern fn main() {
     use process::AppId;
+    use process::scheduler::before;
 
     let mut platform = unsafe {
         platform::init()
@@ -27,32 +33,58 @@ pub extern fn main() {
 
 
     let processes = unsafe {
-        process::process::load_processes(&_sapps)
+        process::process::load_processes(&_sapps, &_eapps_flash,
+                                         platform::FAULT_RESPONSES,
+                                         platform::DRIVER_PERMISSIONS,
+                                         |app, err| {
+            println!("Skipping app at {:?}: {:?}", app, err);
+        })
     };
 
     loop {
         unsafe {
             platform.service_pending_interrupts();
 
-            let mut running_left = false;
-            for (i, p) in processes.iter_mut().enumerate() {
-                p.as_mut().map(|process| {
-                    sched::do_process(platform, process, AppId::new(i));
-                    if process.state == process::State::Running {
-                        running_left = true;
-                    }
-                });
-                if platform.has_pending_interrupts() {
-                    break;
-                }
+            let mut line = [0; shell::MAX_LINE_LEN];
+            if let Some(len) = platform.take_shell_line(&mut line) {
+                shell::run(platform, processes, &line[..len]);
+            }
+
+            for process in processes.iter_mut().filter_map(|p| p.as_mut()) {
+                process.restart_if_due(platform.clock());
             }
 
-            support::atomic(|| {
-                if !platform.has_pending_interrupts() && !running_left {
-                    support::wfi();
+            match platform.scheduler().next(processes) {
+                Some((i, timeslice_us)) => {
+                    let used_us = processes[i].as_mut().map_or(0, |process| {
+                        let appid = AppId::new(i, process.generation());
+                        sched::do_process(platform, process, appid,
+                                          timeslice_us)
+                    });
+                    platform.scheduler().ran(i, used_us);
+                },
+                None => {
+                    // Sleep until the next interrupt, until the scheduler
+                    // lets a process it is holding back run again, or until
+                    // a faulted process is due to restart
+                    let release = platform.scheduler().next_release(processes);
+                    let wakeup = processes.iter()
+                        .filter_map(|p| p.as_ref().and_then(|p| p.restart_at()))
+                        .fold(release, |earliest, at| match earliest {
+                            Some(e) if before(e, at) => Some(e),
+                            _ => Some(at)
+                        });
+                    support::atomic(|| {
+                        let due = wakeup.map_or(false, |when| {
+                            platform.wake_at(when);
+                            !before(platform.clock().now(), when)
+                        });
+                        if !due && !platform.has_pending_interrupts() {
+                            support::wfi();
+                        }
+                    })
                 }
-            })
+            }
         };
     }
 }
-
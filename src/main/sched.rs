@@ -1,53 +1,148 @@
+use hil::ReturnCode;
+use platform;
 use platform::{Platform,systick};
 use process;
 use process::Process;
 use process::{AppSlice,AppId};
+use process::filter::{SyscallKind, Verdict};
 use common::Queue;
 use syscall;
+use fault;
+use trace;
 
+/// Drivers are numbered below this.
+pub const MAX_DRIVER_NUM: usize = 16;
+
+/// Tells every driver that the process `appid` is about to fault, exit, be
+/// terminated or restart. Must be called while its grants are still there.
+pub fn app_exited(platform: &mut Platform, appid: AppId) {
+    for driver_num in 0..MAX_DRIVER_NUM {
+        platform.with_driver(driver_num, |driver| {
+            driver.map(|driver| driver.app_exited(appid));
+        });
+    }
+}
+
+/// Runs `process` for up to `timeslice_us` microseconds, handling its system
+/// calls, until it waits for a callback or an interrupt is pending. Returns
+/// the number of microseconds used.
 pub unsafe fn do_process(platform: &mut Platform, process: &mut Process,
-                  appid: AppId) {
+                  appid: AppId, timeslice_us: u32) -> u32 {
     systick::reset();
-    systick::set_timer(10000);
+    systick::set_timer(timeslice_us);
     systick::enable(true);
 
+    let mut expired = false;
+    let mut switched = false;
     loop {
-        if platform.has_pending_interrupts() ||
-                systick::overflowed() || systick::value() <= 500 {
+        // Don't switch back to the process with less than 500us left, but
+        // always switch to it once, however short its timeslice
+        if systick::overflowed() || (switched && systick::value() <= 500) {
+            expired = true;
+            break;
+        }
+        if platform.has_pending_interrupts() {
             break;
         }
 
-        match process.state {
+        match process.state() {
             process::State::Running => {
-                let (data_start, data_len, text_start, text_len) =
-                        process.memory_regions();
+                let (data, text, guard) = process.memory_regions();
                 // Data segment read/write/execute
                 platform.mpu().set_mpu(
-                    0, data_start as u32, data_len as u32, true, 0b011);
+                    0, data.start as u32, data.mpu_len(),
+                    data.subregions_disabled, true, 0b011);
                 // Text segment read/execute (no write)
                 platform.mpu().set_mpu(
-                    1, text_start as u32, text_len as u32, true, 0b111);
+                    1, text.start as u32, text.mpu_len(),
+                    text.subregions_disabled, true, 0b111);
+                // Stack guard, no access from the process. Overrides the data
+                // segment since higher numbered regions take priority.
+                platform.mpu().set_mpu(
+                    2, guard.start as u32, guard.mpu_len(),
+                    guard.subregions_disabled, false, 0b001);
+                // Buffers other apps shared over IPC, read/write (no execute)
+                let shared = platform.ipc_shared_regions(appid);
+                for (i, region) in shared.iter().enumerate() {
+                    let region_num = 3 + i as u32;
+                    match *region {
+                        Some(r) => platform.mpu().set_mpu(
+                            region_num, r.start as u32, r.mpu_len(),
+                            r.subregions_disabled, false, 0b011),
+                        None => platform.mpu().disable_region(region_num)
+                    }
+                }
                 systick::enable(true);
                 process.switch_to();
                 systick::enable(false);
+                switched = true;
+                if process.app_fault() {
+                    fault::report(platform, process, appid);
+                    app_exited(platform, appid);
+                    process.fault_state(platform.clock());
+                    break;
+                }
             }
             process::State::Waiting => {
                 match process.callbacks.dequeue() {
                     None => { break },
                     Some(cb) => {
-                        process.state = process::State::Running;
+                        process.set_state(process::State::Running);
                         process.push_callback(cb);
                         continue;
                     }
                 }
             }
+            process::State::Unstarted | process::State::Stopped |
+            process::State::StoppedWaiting | process::State::Faulted |
+            process::State::Terminated => { break }
         }
 
         if !process.syscall_fired() {
             break;
         }
 
-        match process.svc_number() {
+        let svc = process.svc_number();
+        svc.map(|svc| {
+            process.stats.syscalls.get_mut(svc as usize).map(|count| *count += 1);
+        });
+        let timestamp = if platform::TRACE_SYSCALLS {
+            platform.clock().now()
+        } else {
+            0
+        };
+        let args = [process.r0(), process.r1(), process.r2(), process.r3()];
+
+        // Drivers the board doesn't let the process use, then whatever else
+        // the platform's syscall filter rules out
+        let kind = match svc {
+            Some(syscall::SUBSCRIBE) => Some(SyscallKind::Subscribe),
+            Some(syscall::COMMAND) => Some(SyscallKind::Command),
+            Some(syscall::ALLOW) => Some(SyscallKind::Allow),
+            _ => None
+        };
+        let verdict = kind.map_or(Verdict::Allow, |kind| {
+            let driver_num = process.r0();
+            if !process.driver_permissions.permits(driver_num) {
+                process.stats.permission_denials += 1;
+                Verdict::Deny
+            } else {
+                platform.syscall_filter().check(appid.idx(), process, kind,
+                                                driver_num, process.r1())
+            }
+        });
+        if verdict == Verdict::Fault {
+            fault::report_syscall_violation(process, appid);
+            app_exited(platform, appid);
+            process.fault_state(platform.clock());
+            break;
+        }
+
+        let ret = match svc {
+            _ if verdict == Verdict::Deny => {
+                process.set_r0(ReturnCode::EPERM.into());
+                ReturnCode::EPERM
+            },
             Some(syscall::MEMOP) => {
                 let brk_type = process.r0();
                 let r1 = process.r1();
@@ -55,22 +150,65 @@ pub unsafe fn do_process(platform: &mut Platform, process: &mut Process,
                 let res = match brk_type {
                     0 /* BRK */ => {
                         process.brk(r1 as *const u8)
-                            .map(|_| 0).unwrap_or(-1)
+                            .map(|_| ReturnCode::SUCCESS)
+                            .unwrap_or_else(|err| err.into())
                     },
                     1 /* SBRK */ => {
                         process.sbrk(r1 as isize)
-                            .map(|addr| addr as isize).unwrap_or(-1)
+                            .map(|addr| {
+                                ReturnCode::SuccessWithValue {
+                                    value: addr as usize
+                                }
+                            })
+                            .unwrap_or_else(|err| err.into())
+                    },
+                    2 /* DROPPED CALLBACKS */ => {
+                        ReturnCode::SuccessWithValue {
+                            value: process.take_dropped_callbacks()
+                        }
+                    },
+                    3 /* MEMORY START */ => {
+                        ReturnCode::SuccessWithValue {
+                            value: process.mem_start() as usize
+                        }
+                    },
+                    4 /* MEMORY END */ => {
+                        ReturnCode::SuccessWithValue {
+                            value: process.mem_end() as usize
+                        }
+                    },
+                    5 /* FLASH START */ => {
+                        ReturnCode::SuccessWithValue {
+                            value: process.flash_start() as usize
+                        }
+                    },
+                    6 /* FLASH SIZE */ => {
+                        ReturnCode::SuccessWithValue {
+                            value: process.flash_size()
+                        }
+                    },
+                    7 /* GRANT REGION START */ => {
+                        ReturnCode::SuccessWithValue {
+                            value: process.app_accessible_end() as usize
+                        }
                     },
-                    _ => -2
+                    8 /* STACK LIMIT */ => {
+                        ReturnCode::SuccessWithValue {
+                            value: process.stack_limit() as usize
+                        }
+                    },
+                    _ => ReturnCode::ENOSUPPORT
                 };
-                process.set_r0(res);
+                process.set_r0(res.into());
+                res
             },
             Some(syscall::WAIT) => {
-                process.state = process::State::Waiting;
+                process.set_state(process::State::Waiting);
                 process.pop_syscall_stack();
 
-                // There might be already enqueued callbacks
-                continue;
+                // There might be already enqueued callbacks, which the next
+                // time around the loop picks up
+                ReturnCode::SUCCESS
             },
             Some(syscall::SUBSCRIBE) => {
                 let driver_num = process.r0();
@@ -84,21 +222,24 @@ pub unsafe fn do_process(platform: &mut Platform, process: &mut Process,
                     match driver {
                         Some(d) => d.subscribe(subdriver_num,
                                                callback),
-                        None => -1
+                        None => ReturnCode::ENODEVICE
                     }
                 });
-                process.set_r0(res);
+                process.set_r0(res.into());
+                res
             },
             Some(syscall::COMMAND) => {
                 let res = platform.with_driver(process.r0(), |driver| {
                     match driver {
-                        Some(d) => d.command(process.r1(),
-                                             process.r2(),
-                                             appid),
-                        None => -1
+                        Some(d) => d.command2(process.r1(),
+                                              process.r2(),
+                                              process.r3(),
+                                              appid),
+                        None => ReturnCode::ENODEVICE
                     }
                 });
-                process.set_r0(res);
+                process.set_r0(res.into());
+                res
             },
             Some(syscall::ALLOW) => {
                 let res = platform.with_driver(process.r0(), |driver| {
@@ -106,20 +247,57 @@ pub unsafe fn do_process(platform: &mut Platform, process: &mut Process,
                         Some(d) => {
                             let start_addr = process.r2() as *mut u8;
                             let size = process.r3();
-                            if process.in_exposed_bounds(start_addr, size) {
-                                let slice = AppSlice::new(start_addr as *mut u8, size, appid);
-                                d.allow(appid, process.r1(), slice)
-                            } else {
-                                -1
+                            match process.expose(start_addr, size) {
+                                Ok(()) => {
+                                    let slice = AppSlice::new(start_addr as *mut u8, size, appid);
+                                    d.allow(appid, process.r1(), slice)
+                                },
+                                Err(err) => err.into()
                             }
                         },
-                        None => -1
+                        None => ReturnCode::ENODEVICE
                     }
                 });
-                process.set_r0(res);
+                process.set_r0(res.into());
+                res
+            }
+            Some(syscall::EXIT) => {
+                // The process never sees a return value, and the next time
+                // around the loop finds it terminated
+                app_exited(platform, appid);
+                process.terminate();
+                ReturnCode::SUCCESS
+            },
+            _ => {
+                process.set_r0(ReturnCode::ENOSUPPORT.into());
+                ReturnCode::ENOSUPPORT
+            }
+        };
+        if platform::TRACE_SYSCALLS {
+            if let Some(svc) = svc {
+                // WAIT and EXIT don't return to the app
+                let ret = match svc {
+                    syscall::WAIT | syscall::EXIT => None,
+                    _ => Some(ret.into())
+                };
+                trace::record(timestamp, appid.idx(), svc, args, ret);
             }
-            _ => {}
         }
     }
+    // Only count timeslices in which the process actually ran
+    if switched {
+        process.stats.timeslices += 1;
+    }
+    if expired {
+        process.stats.timeslice_expirations += 1;
+    }
+    let used_us = if !switched {
+        0
+    } else if systick::overflowed() {
+        timeslice_us
+    } else {
+        timeslice_us.saturating_sub(systick::value())
+    };
     systick::reset();
+    used_us
 }
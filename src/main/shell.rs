@@ -0,0 +1,161 @@
+//! A kernel shell on the console.
+//!
+//! Lines typed on the console that start with `!` go to the kernel instead of
+//! apps (see `drivers::console`). The shell lists processes, starts apps
+//! their header marks as disabled, stops, resumes, restarts and kills
+//! processes by index, and shows how much grant memory each
+//! driver is using for each process and the CPU and system call counters the
+//! kernel keeps for each process, including how many system calls its driver
+//! permissions or the platform's syscall filter denied. On platforms that keep
+//! a trace, `trace` dumps the most recent system calls in binary (see the
+//! `trace` module):
+//!
+//! ```text
+//! !ps
+//! !stats
+//! !start 1
+//! !stop 1
+//! !resume 1
+//! !restart 1
+//! !kill 1
+//! !grants
+//! !trace
+//! ```
+
+use core::str;
+use platform;
+use platform::Platform;
+use process::{AppId, Process, State};
+use sched;
+use syscall;
+use trace;
+
+/// Longest line the shell accepts, as much as the console keeps.
+pub const MAX_LINE_LEN: usize = platform::SHELL_LINE_LEN;
+
+pub unsafe fn run(platform: &mut Platform,
+                  processes: &mut [Option<Process<'static>>], line: &[u8]) {
+    // The shell writes to the console UART directly, so let the console
+    // driver finish sending app output first rather than interleave with it
+    platform.wait_for_console();
+
+    let line = match str::from_utf8(line) {
+        Ok(line) => line,
+        Err(_) => {
+            print!("Commands are ASCII\r\n");
+            return;
+        }
+    };
+    let mut words = line.split(' ').filter(|word| !word.is_empty());
+    let command = words.next().unwrap_or("");
+    let index = words.next().and_then(|word| word.parse::<usize>().ok());
+
+    match command {
+        "ps" => list_processes(platform, processes),
+        "grants" => list_grants(platform, processes),
+        "stats" => list_statistics(platform, processes),
+        "trace" if platform::TRACE_SYSCALLS => {
+            trace::dump(platform.clock().frequency());
+            print!("\r\n");
+        },
+        "trace" => print!("Tracing is off\r\n"),
+        "start" | "stop" | "resume" | "restart" | "kill" => {
+            let (i, process) = match index.and_then(|i| {
+                processes.get_mut(i).and_then(|slot| slot.as_mut())
+                                    .map(|process| (i, process))
+            }) {
+                Some(found) => found,
+                None => {
+                    print!("No such process\r\n");
+                    return;
+                }
+            };
+            // Drivers never saw unstarted processes, and already forgot ones
+            // that faulted, exited or were killed
+            let live = match process.state() {
+                State::Unstarted | State::Faulted | State::Terminated => false,
+                _ => true
+            };
+            if live && (command == "restart" || command == "kill") {
+                sched::app_exited(platform, AppId::new(i, process.generation()));
+            }
+            match command {
+                "start" => process.start(),
+                "stop" => process.stop(),
+                "resume" => process.resume(),
+                "restart" => {
+                    if process.restart().is_err() {
+                        print!("Not enough memory to restart\r\n");
+                    }
+                },
+                _ => process.terminate()
+            }
+            print!("{:?}\r\n", process.state());
+        },
+        _ => {
+            print!("Commands: ps, stats, grants, trace, start N, stop N, \
+                    resume N, restart N, kill N\r\n");
+        }
+    }
+}
+
+unsafe fn list_processes(platform: &mut Platform,
+                         processes: &[Option<Process<'static>>]) {
+    print!(" # Name              Memory                 App break   \
+            Kernel break  Callbacks  Missed  State\r\n");
+    for (i, slot) in processes.iter().enumerate() {
+        if let Some(ref process) = *slot {
+            print!("{:2} {:16}  0x{:08x}-0x{:08x}  0x{:08x}  0x{:08x}    \
+                    {:4}/{:<4}  {:6}  {:?}\r\n",
+                   i, process.name(),
+                   process.mem_start() as usize, process.mem_end() as usize,
+                   process.app_memory_break() as usize,
+                   process.kernel_memory_break() as usize,
+                   process.callbacks.len(), process.callback_queue_len(),
+                   platform.scheduler().deadline_misses(i),
+                   process.state());
+        }
+    }
+}
+
+unsafe fn list_grants(platform: &mut Platform,
+                      processes: &[Option<Process<'static>>]) {
+    print!("Driver  Grant bytes per process\r\n");
+    for driver_num in 0..sched::MAX_DRIVER_NUM {
+        platform.with_driver(driver_num, |driver| {
+            driver.map(|driver| {
+                print!("{:6} ", driver_num);
+                for (i, slot) in processes.iter().enumerate() {
+                    if let Some(ref process) = *slot {
+                        let appid = AppId::new(i, process.generation());
+                        print!("  {}: {:5}", i, driver.grant_bytes(appid));
+                    }
+                }
+                print!("\r\n");
+            });
+        });
+    }
+}
+
+fn list_statistics(platform: &mut Platform,
+                   processes: &[Option<Process<'static>>]) {
+    print!(" # Timeslices  Expired    Wait  Subscr Command   Allow   Memop  \
+            Callbacks  Dropped  Denied  Restarts\r\n");
+    for (i, slot) in processes.iter().enumerate() {
+        if let Some(ref process) = *slot {
+            let stats = &process.stats;
+            print!("{:2} {:10} {:8} {:7} {:7} {:7} {:7} {:7} {:10} {:8} \
+                    {:7} {:9}\r\n",
+                   i, stats.timeslices, stats.timeslice_expirations,
+                   stats.syscalls[syscall::WAIT as usize],
+                   stats.syscalls[syscall::SUBSCRIBE as usize],
+                   stats.syscalls[syscall::COMMAND as usize],
+                   stats.syscalls[syscall::ALLOW as usize],
+                   stats.syscalls[syscall::MEMOP as usize],
+                   stats.callbacks_queued, stats.callbacks_dropped,
+                   stats.permission_denials +
+                       platform.syscall_filter().violations(i),
+                   process.restarts());
+        }
+    }
+}
@@ -0,0 +1,100 @@
+//! A trace of the most recent system calls.
+//!
+//! If the platform sets `TRACE_SYSCALLS`, `sched::do_process` records every
+//! system call apps make in a ring buffer holding the last `TRACE_LEN`. The
+//! kernel shell's `trace` command dumps it over the console in a compact
+//! binary form, which `tools/trace/decode.py` turns back into a table. The
+//! shell waits for the console driver to finish sending app output first, so
+//! the dump isn't interleaved with it.
+//!
+//! A dump starts with the bytes `TRC1`, followed by the number of records and
+//! the frequency of their timestamps in Hz, each a 32-bit word. The records
+//! follow, oldest first, 28 bytes each:
+//!
+//! ```text
+//! Offset  Size  Field
+//!  0      4     Timestamp when the system call was made, in clock ticks
+//!  4      1     Process index
+//!  5      1     SVC number
+//!  6      1     Flags: bit 0 is set if the call didn't return to the app
+//!  7      1     Zero
+//!  8      16    r0-r3 as passed to the system call
+//! 24      4     Value returned to the app in r0, or zero if it didn't return
+//! ```
+//!
+//! All words are little-endian.
+
+use platform;
+
+/// Number of system calls kept.
+const TRACE_LEN: usize = 64;
+
+const MAGIC: &'static [u8; 4] = b"TRC1";
+
+/// Set in a record's flags for calls, like WAIT and EXIT, that didn't return.
+const NO_RETURN: u8 = 0x1;
+
+#[derive(Copy,Clone)]
+struct Record {
+    timestamp: u32,
+    app: u8,
+    svc: u8,
+    args: [usize; 4],
+    ret: Option<isize>
+}
+
+const EMPTY: Record = Record {
+    timestamp: 0,
+    app: 0,
+    svc: 0,
+    args: [0; 4],
+    ret: None
+};
+
+static mut RECORDS: [Record; TRACE_LEN] = [EMPTY; TRACE_LEN];
+
+/// Where the next record goes.
+static mut NEXT: usize = 0;
+
+/// Number of valid records, at most `TRACE_LEN`.
+static mut COUNT: usize = 0;
+
+/// Records a system call, overwriting the oldest once the buffer is full.
+/// `ret` is `None` for calls that don't return to the app.
+pub unsafe fn record(timestamp: u32, app: usize, svc: u8, args: [usize; 4],
+                     ret: Option<isize>) {
+    RECORDS[NEXT] = Record {
+        timestamp: timestamp,
+        app: app as u8,
+        svc: svc,
+        args: args,
+        ret: ret
+    };
+    NEXT = (NEXT + 1) % TRACE_LEN;
+    if COUNT < TRACE_LEN {
+        COUNT += 1;
+    }
+}
+
+/// Writes the trace to the console, oldest record first. `frequency` is the
+/// frequency of the clock the timestamps came from.
+pub unsafe fn dump(frequency: u32) {
+    let writer = &mut platform::io::WRITER;
+    writer.write_bytes(MAGIC);
+    writer.write_bytes(&word(COUNT as u32));
+    writer.write_bytes(&word(frequency));
+    for i in 0..COUNT {
+        let record = &RECORDS[(NEXT + TRACE_LEN - COUNT + i) % TRACE_LEN];
+        writer.write_bytes(&word(record.timestamp));
+        let flags = if record.ret.is_none() { NO_RETURN } else { 0 };
+        writer.write_bytes(&[record.app, record.svc, flags, 0]);
+        for &arg in record.args.iter() {
+            writer.write_bytes(&word(arg as u32));
+        }
+        writer.write_bytes(&word(record.ret.unwrap_or(0) as u32));
+    }
+}
+
+fn word(value: u32) -> [u8; 4] {
+    [value as u8, (value >> 8) as u8, (value >> 16) as u8, (value >> 24) as u8]
+}
@@ -27,6 +27,43 @@ use nrf51822::timer::ALARM1;
 
 pub mod systick;
 
+/// What the kernel does when each app, in the order they appear in flash,
+/// faults. Apps past the end of this list are stopped.
+pub static FAULT_RESPONSES: &'static [process::FaultResponse] = &[];
+
+/// Which drivers each app, in the order they appear in flash, may use. Apps
+/// past the end of this list may use all of them.
+pub static DRIVER_PERMISSIONS: &'static [process::DriverPermissions] = &[];
+
+/// Whether the kernel keeps a trace of the most recent system calls for the
+/// shell's `trace` command. Off to save RAM.
+pub const TRACE_SYSCALLS: bool = false;
+
+/// Longest line the console keeps for the kernel shell. There is no console
+/// here, so no line ever arrives.
+pub const SHELL_LINE_LEN: usize = drivers::console::SHELL_LINE_LEN;
+
+static mut SCHEDULER: process::scheduler::RoundRobin =
+    process::scheduler::RoundRobin::new();
+
+static mut SYSCALL_FILTER: process::filter::AllowAll =
+    process::filter::AllowAll;
+
+/// Stands in for a clock until one is set up for the kernel.
+struct NoClock;
+
+impl process::scheduler::Clock for NoClock {
+    fn now(&self) -> u32 {
+        0
+    }
+
+    fn frequency(&self) -> u32 {
+        1
+    }
+}
+
+static NO_CLOCK: NoClock = NoClock;
+
 pub struct Firestorm {
     chip: nrf51822::chip::Nrf51822,
     gpio: &'static drivers::gpio::GPIO<'static, nrf51822::gpio::GPIOPin>,
@@ -36,7 +73,10 @@ pub struct Firestorm {
 pub struct DummyMPU;
 
 impl DummyMPU {
-    pub fn set_mpu(&mut self, _: u32, _: u32, _: u32, _: bool, _: u32) {
+    pub fn set_mpu(&mut self, _: u32, _: u32, _: u32, _: u8, _: bool, _: u32) {
+    }
+
+    pub fn disable_region(&mut self, _: u32) {
     }
 }
 
@@ -53,6 +93,39 @@ impl Firestorm {
         DummyMPU
     }
 
+    pub fn scheduler(&mut self) -> &'static mut process::Scheduler {
+        unsafe { &mut SCHEDULER }
+    }
+
+    pub fn syscall_filter(&mut self) -> &'static mut process::SyscallFilter {
+        unsafe { &mut SYSCALL_FILTER }
+    }
+
+    /// There is no kernel clock, so timestamps are all zero.
+    pub fn clock(&mut self) -> &'static process::scheduler::Clock {
+        &NO_CLOCK
+    }
+
+    /// There is no kernel clock to wake up by, so the kernel sleeps until the
+    /// next interrupt regardless.
+    pub fn wake_at(&mut self, _: u32) {
+    }
+
+    /// There is no console to wait for.
+    pub unsafe fn wait_for_console(&mut self) {
+    }
+
+    /// There is no console, so no kernel shell either.
+    pub fn take_shell_line(&mut self, _: &mut [u8]) -> Option<usize> {
+        None
+    }
+
+    /// There is no IPC driver, so no app shares memory with another.
+    pub fn ipc_shared_regions(&mut self, _: process::AppId)
+            -> [Option<process::MemoryRegion>; process::NUM_PROCS] {
+        [None; process::NUM_PROCS]
+    }
+
     #[inline(never)]
     pub fn with_driver<F, R>(&mut self, driver_num: usize, f: F) -> R where
             F: FnOnce(Option<&hil::Driver>) -> R {
@@ -8,8 +8,9 @@ pub struct Writer { initialized: bool }
 
 pub static mut WRITER : Writer = Writer { initialized: false };
 
-impl Write for Writer {
-    fn write_str(&mut self, s: &str) -> ::core::fmt::Result {
+impl Writer {
+    /// Sends raw bytes, for output that isn't text.
+    pub fn write_bytes(&mut self, bytes: &[u8]) {
         let uart = unsafe { &mut sam4l::usart::USART3 };
         if !self.initialized {
             self.initialized = true;
@@ -22,9 +23,15 @@ impl Write for Writer {
             uart.enable_tx();
 
         }
-        for c in s.bytes() {
+        for &c in bytes {
             uart.send_byte(c);
         }
+    }
+}
+
+impl Write for Writer {
+    fn write_str(&mut self, s: &str) -> ::core::fmt::Result {
+        self.write_bytes(s.as_bytes());
         Ok(())
     }
 }
@@ -12,6 +12,7 @@ extern crate support;
 extern crate process;
 
 use hil::Controller;
+use hil::alarm::{Alarm, AlarmClient, Frequency};
 use hil::spi_master::SpiMaster;
 use hil::gpio::GPIOPin;
 use drivers::virtual_alarm::{MuxAlarm, VirtualMuxAlarm};
@@ -29,6 +30,101 @@ pub mod systick;
 //mod spi_dummy;
 //mod i2c_dummy;
 
+/// Restart apps up to 10 times, waiting 100ms before the first restart and
+/// doubling that each time up to 10s.
+const RESTART_POLICY: process::RestartPolicy =
+    process::RestartPolicy::new(10, 100, 10000);
+
+/// What the kernel does when each app, in the order they appear in flash,
+/// faults. Apps past the end of this list are stopped.
+pub static FAULT_RESPONSES: &'static [process::FaultResponse] = &[
+    process::FaultResponse::Restart(RESTART_POLICY),
+    process::FaultResponse::Restart(RESTART_POLICY),
+];
+
+/// Which drivers each app, in the order they appear in flash, may use. Apps
+/// past the end of this list may use all of them. For example, to keep the
+/// second app off the SPI bus (driver 4) the RF233 radio sits on:
+///
+/// ```ignore
+/// pub static DRIVER_PERMISSIONS: &'static [process::DriverPermissions] = &[
+///     process::DriverPermissions::all(),
+///     process::DriverPermissions::new(!(1 << 4)),
+/// ];
+/// ```
+pub static DRIVER_PERMISSIONS: &'static [process::DriverPermissions] = &[];
+
+/// Whether the kernel keeps a trace of the most recent system calls for the
+/// shell's `trace` command. Costs about 2kB of RAM and a little time on every
+/// system call.
+pub const TRACE_SYSCALLS: bool = true;
+
+/// Longest line the console keeps for the kernel shell.
+pub const SHELL_LINE_LEN: usize = drivers::console::SHELL_LINE_LEN;
+
+/// Lets the scheduler read the time from the AST.
+struct AstClock;
+
+impl process::scheduler::Clock for AstClock {
+    fn now(&self) -> u32 {
+        unsafe { sam4l::ast::AST.now() }
+    }
+
+    fn frequency(&self) -> u32 {
+        <<sam4l::ast::Ast as Alarm>::Frequency as Frequency>::frequency()
+    }
+}
+
+static AST_CLOCK: AstClock = AstClock;
+
+/// Client of the alarm that wakes the kernel up for `Platform::wake_at`. The
+/// interrupt is all it takes, so there is nothing to do when it fires.
+struct KernelWakeup;
+
+impl AlarmClient for KernelWakeup {
+    fn fired(&self) {}
+}
+
+static KERNEL_WAKEUP: KernelWakeup = KernelWakeup;
+
+/// Apps that declare a period in their header are scheduled by earliest
+/// deadline. The rest run when no periodic app needs to, by the priority in
+/// their header, with apps of equal priority taking turns.
+static mut SCHEDULER: process::scheduler::EarliestDeadlineFirst<'static> =
+    process::scheduler::EarliestDeadlineFirst::new(&AST_CLOCK);
+
+/// Limits on the system calls each app, in the order they appear in flash, may
+/// make, on top of `DRIVER_PERMISSIONS`. Apps past the end of this list may
+/// make any system call. For example, to let the second app make at most 100
+/// SPI transfers (driver 4, command 1) a second and keep it off the chip
+/// select command, faulting it if it tries either:
+///
+/// ```ignore
+/// static SYSCALL_POLICIES: &'static [process::filter::Policy] = &[
+///     process::filter::UNRESTRICTED,
+///     process::filter::Policy {
+///         rules: &[process::filter::Rule {
+///             kind: Some(process::filter::SyscallKind::Command),
+///             driver_num: 4,
+///             minor_num: Some(2),
+///             allow: false
+///         }],
+///         allow_by_default: true,
+///         rate_limits: &[process::filter::RateLimit {
+///             driver_num: 4,
+///             minor_num: Some(1),
+///             max: 100,
+///             period_ms: 1000
+///         }],
+///         fault_on_violation: true
+///     },
+/// ];
+/// ```
+static SYSCALL_POLICIES: &'static [process::filter::Policy] = &[];
+
+static mut SYSCALL_FILTER: process::filter::PolicyFilter<'static> =
+    process::filter::PolicyFilter::new(&AST_CLOCK);
+
 static mut spi_read_buf:  [u8; 64] = [0; 64];
 static mut spi_write_buf: [u8; 64] = [0; 64];
 
@@ -42,6 +138,9 @@ pub struct Platform {
     isl29035: &'static drivers::isl29035::Isl29035<'static>,
     spi: &'static drivers::spi::Spi<'static, sam4l::spi::Spi>,
     nrf51822: &'static drivers::nrf51822_serialization::Nrf51822Serialization<'static, sam4l::usart::USART>,
+    ipc: &'static drivers::ipc::IPC,
+    process_info: &'static drivers::process_info::ProcessInfo,
+    wakeup: &'static VirtualMuxAlarm<'static, sam4l::ast::Ast>,
 }
 
 impl Platform {
@@ -57,6 +156,52 @@ impl Platform {
         &mut self.chip.mpu
     }
 
+    pub fn scheduler(&mut self) -> &'static mut process::Scheduler {
+        unsafe { &mut SCHEDULER }
+    }
+
+    pub fn syscall_filter(&mut self) -> &'static mut process::SyscallFilter {
+        unsafe { &mut SYSCALL_FILTER }
+    }
+
+    /// The AST, for timestamps.
+    pub fn clock(&mut self) -> &'static process::scheduler::Clock {
+        &AST_CLOCK
+    }
+
+    /// Makes sure the kernel wakes up from sleep once the clock reaches
+    /// `when`, e.g. for the scheduler to release a job.
+    pub fn wake_at(&mut self, when: u32) {
+        self.wakeup.set_alarm(when);
+    }
+
+    /// MPU regions for the buffers other apps shared with `appid` over IPC,
+    /// indexed by the sharing app.
+    pub fn ipc_shared_regions(&mut self, appid: process::AppId)
+            -> [Option<process::MemoryRegion>; process::NUM_PROCS] {
+        self.ipc.shared_regions(appid)
+    }
+
+    /// Services interrupts until the console driver has finished sending app
+    /// output, including writes queued behind the current one, so the kernel
+    /// can write to the console UART directly.
+    pub unsafe fn wait_for_console(&mut self) {
+        while self.console.transmitting() {
+            self.chip.service_pending_interrupts();
+        }
+    }
+
+    /// Copies a line typed for the kernel shell on the console into `line`,
+    /// returning its length, if one is waiting.
+    pub fn take_shell_line(&mut self, line: &mut [u8]) -> Option<usize> {
+        self.console.take_shell_line(line)
+    }
+
+    /// Reads and clears the fault status registers.
+    pub fn fault_status(&mut self) -> cortexm4::scb::FaultStatus {
+        unsafe { cortexm4::scb::FaultStatus::read_and_clear() }
+    }
+
     pub fn with_driver<F, R>(&mut self, driver_num: usize, f: F) -> R where
             F: FnOnce(Option<&hil::Driver>) -> R {
 
@@ -68,6 +213,8 @@ impl Platform {
             4 => f(Some(self.spi)),
             5 => f(Some(self.nrf51822)),
             6 => f(Some(self.isl29035)),
+            7 => f(Some(self.ipc)),
+            8 => f(Some(self.process_info)),
             _ => f(None)
         }
     }
@@ -295,11 +442,14 @@ pub unsafe fn init() -> &'static mut Platform {
 
     set_pin_primary_functions();
 
+    SYSCALL_FILTER.set_policies(SYSCALL_POLICIES);
+
     static_init!(console: drivers::console::Console<sam4l::usart::USART> =
                      drivers::console::Console::new(&sam4l::usart::USART3,
                                                     &mut drivers::console::WRITE_BUF,
+                                                    &mut drivers::console::SHELL_LINE_BUF,
                                                     process::Container::create()),
-                 24);
+                 44);
     sam4l::usart::USART3.set_client(console);
 
     // Create the Nrf51822Serialization driver for passing BLE commands
@@ -309,7 +459,7 @@ pub unsafe fn init() -> &'static mut Platform {
             drivers::nrf51822_serialization::Nrf51822Serialization::new(
                 &sam4l::usart::USART2,
                 &mut drivers::nrf51822_serialization::WRITE_BUF
-            ), 124);
+            ), 284);
     sam4l::usart::USART2.set_client(nrf_serialization);
 
     let ast = &sam4l::ast::AST;
@@ -317,6 +467,9 @@ pub unsafe fn init() -> &'static mut Platform {
     static_init!(mux_alarm: MuxAlarm<'static, sam4l::ast::Ast> = MuxAlarm::new(&sam4l::ast::AST),
                  16);
     ast.configure(mux_alarm);
+    // Start the counter now rather than with the first alarm, since the
+    // scheduler reads the time from it
+    ast.enable();
 
     static_init!(mux_i2c: MuxI2C<'static> = MuxI2C::new(&sam4l::i2c::I2C2),
                  20);
@@ -330,7 +483,7 @@ pub unsafe fn init() -> &'static mut Platform {
                      drivers::tmp006::TMP006::new(tmp006_i2c,
                                                   &sam4l::gpio::PA[9],
                                                   &mut drivers::tmp006::BUFFER),
-                 52);
+                 56);
     tmp006_i2c.set_client(tmp006);
     sam4l::gpio::PA[9].set_client(tmp006);
 
@@ -340,7 +493,7 @@ pub unsafe fn init() -> &'static mut Platform {
                  32);
     static_init!(isl29035: drivers::isl29035::Isl29035<'static> =
                      drivers::isl29035::Isl29035::new(isl29035_i2c, &mut drivers::isl29035::BUF),
-                 36);
+                 40);
     isl29035_i2c.set_client(isl29035);
 
     static_init!(virtual_alarm1: VirtualMuxAlarm<'static, sam4l::ast::Ast> =
@@ -353,10 +506,15 @@ pub unsafe fn init() -> &'static mut Platform {
                  12);
     virtual_alarm1.set_client(timer);
 
+    static_init!(wakeup_alarm: VirtualMuxAlarm<'static, sam4l::ast::Ast> =
+                     VirtualMuxAlarm::new(mux_alarm),
+                 24);
+    wakeup_alarm.set_client(&KERNEL_WAKEUP);
+
     // Initialize and enable SPI HAL
     static_init!(spi: drivers::spi::Spi<'static, sam4l::spi::Spi> =
                      drivers::spi::Spi::new(&mut sam4l::spi::SPI),
-                 140);
+                 300);
     spi.config_buffers(&mut spi_read_buf, &mut spi_write_buf);
     sam4l::spi::SPI.init(spi as &hil::spi_master::SpiCallback);
 
@@ -380,7 +538,7 @@ pub unsafe fn init() -> &'static mut Platform {
     );
     static_init!(gpio: drivers::gpio::GPIO<'static, sam4l::gpio::GPIOPin> =
                      drivers::gpio::GPIO::new(gpio_pins),
-                 20);
+                 24);
     for pin in gpio_pins.iter() {
         pin.set_client(gpio);
     }
@@ -392,6 +550,15 @@ pub unsafe fn init() -> &'static mut Platform {
     // &sam4l::gpio::PA[14] // No Connection
     //
 
+    static_init!(ipc: drivers::ipc::IPC =
+                     drivers::ipc::IPC::new(process::Container::create()),
+                 4);
+
+    static_init!(process_info: drivers::process_info::ProcessInfo =
+                     drivers::process_info::ProcessInfo::new(
+                         process::Container::create()),
+                 4);
+
     static_init!(firestorm: Platform = Platform {
                      chip: sam4l::chip::Sam4l::new(),
                      console: console,
@@ -401,8 +568,11 @@ pub unsafe fn init() -> &'static mut Platform {
                      isl29035: isl29035,
                      spi: spi,
                      nrf51822: nrf_serialization,
+                     ipc: ipc,
+                     process_info: process_info,
+                     wakeup: wakeup_alarm,
                  },
-                 32);
+                 44);
 
     sam4l::usart::USART3.configure(sam4l::usart::USARTParams {
         //client: &console,
@@ -1,19 +1,32 @@
 use core::nonzero::NonZero;
 use process;
 
-#[derive(Clone,Copy)]
+/// Identifies a process. The generation tells apart successive runs of the
+/// process in the same slot, so an `AppId` kept by a driver stops referring to
+/// the process once it is restarted or stopped.
+#[derive(Clone,Copy,PartialEq,Eq)]
 pub struct AppId {
-    idx: usize
+    idx: usize,
+    generation: usize
 }
 
 impl AppId {
-    pub unsafe fn new(idx: usize) -> AppId {
-        AppId {idx: idx}
+    pub unsafe fn new(idx: usize, generation: usize) -> AppId {
+        AppId {idx: idx, generation: generation}
     }
 
     pub fn idx(&self) -> usize {
         self.idx
     }
+
+    pub fn generation(&self) -> usize {
+        self.generation
+    }
+
+    /// Whether the process this refers to is still running.
+    pub fn is_live(&self) -> bool {
+        process::lookup(*self).is_some()
+    }
 }
 
 #[derive(Clone, Copy)]
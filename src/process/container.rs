@@ -1,11 +1,11 @@
 use callback::AppId;
 use core::intrinsics::{volatile_load, volatile_store};
 use core::marker::PhantomData;
-use core::mem::size_of;
+use core::mem::{self, size_of};
 use core::ops::{Deref, DerefMut};
 use core::ptr::Unique;
 use core::raw::Repr;
-use process::{self, Error};
+use process::{self, Error, State};
 
 pub static mut CONTAINER_COUNTER : usize = 0;
 
@@ -15,56 +15,61 @@ pub struct Container<T: Default> {
 }
 
 pub struct AppliedContainer<T> {
-    appid: usize,
+    appid: AppId,
+    container_num: usize,
     container: *mut T,
     _phantom: PhantomData<T>
 }
 
 impl<T> AppliedContainer<T> {
-    pub fn enter<F,R>(self, fun: F) -> R
+    /// Runs `fun` on the container, or returns `Error::NoSuchApp` if the
+    /// process has died since the container was looked up.
+    pub fn enter<F,R>(self, fun: F) -> Result<R, Error>
         where F: FnOnce(&mut Owned<T>, &mut Allocator) -> R, R: Copy {
+        let app = match process::lookup(self.appid) {
+            Some(app) => app,
+            None => return Err(Error::NoSuchApp)
+        };
         let mut allocator = Allocator {
-            app: unsafe { process::PROCS[self.appid].as_mut().unwrap() },
-            app_id: self.appid
+            app: app,
+            app_id: self.appid,
+            container_num: self.container_num
         };
         let mut root = unsafe { Owned::new(self.container, self.appid) };
-        fun(&mut root, &mut allocator)
+        let res = fun(&mut root, &mut allocator);
+        // The root lives as long as the process, so don't free it
+        mem::forget(root);
+        Ok(res)
     }
 }
 
 pub struct Allocator<'a> {
     app: &'a mut process::Process<'a>,
-    app_id: usize
+    app_id: AppId,
+    container_num: usize
 }
 
 pub struct Owned<T: ?Sized> {
     data: Unique<T>,
-    app_id: usize
+    app_id: AppId
 }
 
 impl<T: ?Sized> Owned<T> {
-    pub unsafe fn new(data: *mut T, app_id: usize) -> Owned<T> {
+    pub unsafe fn new(data: *mut T, app_id: AppId) -> Owned<T> {
         Owned { data: Unique::new(data), app_id: app_id }
     }
 
     pub fn appid(&self) -> AppId {
-        unsafe {
-            AppId::new(self.app_id)
-        }
+        self.app_id
     }
 }
 
 impl<T: ?Sized> Drop for Owned<T> {
     fn drop(&mut self) {
         unsafe {
-            let app_id = self.app_id;
             let data = self.data.get_mut() as *mut T as *mut u8;
-            match process::PROCS[app_id] {
-                None => {},
-                Some(ref mut app) => {
-                    app.free(data);
-                }
-            }
+            // If the process died its grant memory is already gone
+            process::lookup(self.app_id).map(|app| app.free(data));
         }
     }
 }
@@ -86,7 +91,7 @@ impl<'a> Allocator<'a> {
     pub fn alloc<T>(&mut self, data: T) -> Result<Owned<T>, Error> {
         unsafe {
             let app_id = self.app_id;
-            self.app.alloc(size_of::<T>()).map_or(Err(Error::OutOfMemory),
+            self.app.alloc(size_of::<T>(), self.container_num).map_or(Err(Error::OutOfMemory),
                 |arr| {
                     let mut owned = Owned::new(arr.repr().data as *mut T, app_id);
                     *owned = data;
@@ -107,40 +112,38 @@ impl<T: Default> Container<T> {
     }
 
     pub fn container(&self, appid: AppId) -> Option<AppliedContainer<T>> {
-        unsafe {
-            let app_id = appid.idx();
-            match process::PROCS[app_id] {
-                Some(ref mut app) => {
-                    let cntr = app.container_for::<T>(self.container_num);
-                    if cntr.is_null() {
-                        None
-                    } else {
-                        Some(AppliedContainer {
-                            appid: app_id,
-                            container: *cntr,
-                            _phantom: PhantomData
-                        })
-                    }
-                },
-                None => None
+        process::lookup(appid).and_then(|app| unsafe {
+            let cntr = app.container_for::<T>(self.container_num);
+            if (*cntr).is_null() {
+                None
+            } else {
+                Some(AppliedContainer {
+                    appid: appid,
+                    container_num: self.container_num,
+                    container: *cntr,
+                    _phantom: PhantomData
+                })
             }
-        }
+        })
     }
 
+    /// Runs `fun` on `appid`'s container, allocating it first if needed.
+    /// Fails with `Error::NoSuchApp` if the process has died.
     pub fn enter<F, R>(&self, appid: AppId, fun: F) -> Result<R, Error>
         where F: FnOnce(&mut Owned<T>, &mut Allocator) -> R, R: Copy {
         unsafe {
-            let app_id = appid.idx();
-            match process::PROCS[app_id] {
-                Some(ref mut app) => {
+            match process::lookup(appid) {
+                Some(app) => {
                     app.container_for_or_alloc::<T>(self.container_num).map_or(
                         Err(Error::OutOfMemory), move |root_ptr| {
-                            let mut root = Owned::new(root_ptr, app_id);
+                            let mut root = Owned::new(root_ptr, appid);
                             let mut allocator = Allocator {
                                 app: app,
-                                app_id: app_id
+                                app_id: appid,
+                                container_num: self.container_num
                             };
                             let res = fun(&mut root, &mut allocator);
+                            mem::forget(root);
                             Ok(res)
                     })
                 },
@@ -149,15 +152,29 @@ impl<T: Default> Container<T> {
         }
     }
 
+    /// Bytes of `appid`'s grant memory used by this container.
+    pub fn grant_bytes(&self, appid: AppId) -> usize {
+        process::lookup(appid).map_or(0, |app| {
+            app.grant_bytes(self.container_num)
+        })
+    }
+
     pub fn each<F>(&self, fun: F) where F: Fn(&mut Owned<T>) {
         unsafe {
-            let itr = process::PROCS.iter_mut().filter_map(|p| p.as_mut());
-            for (app_id, app) in itr.enumerate() {
+            for (idx, slot) in process::PROCS.iter_mut().enumerate() {
+                // Terminated processes have given their memory back
+                let app = match *slot {
+                    Some(ref mut app)
+                        if app.state() != State::Terminated => app,
+                    _ => continue
+                };
                 let ctr_ptr = app.container_for::<T>(self.container_num);
-                if !ctr_ptr.is_null() {
+                if !(*ctr_ptr).is_null() {
                     let root_ptr = *ctr_ptr;
-                    let mut root = Owned::new(root_ptr, app_id);
+                    let appid = AppId::new(idx, app.generation());
+                    let mut root = Owned::new(root_ptr, appid);
                     fun(&mut root);
+                    mem::forget(root);
                 }
             }
         }
@@ -187,7 +204,12 @@ impl<'a, T: Default> Iterator for Iter<'a, T> {
         while self.index < self.len {
             let idx = self.index;
             self.index += 1;
-            let res = self.container.container(unsafe { AppId::new(idx) });
+            let appid = unsafe {
+                process::PROCS[idx].as_ref().map(|app| {
+                    AppId::new(idx, app.generation())
+                })
+            };
+            let res = appid.and_then(|appid| self.container.container(appid));
             if res.is_some() {
                 return res;
             }
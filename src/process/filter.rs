@@ -0,0 +1,210 @@
+//! Policies for which system calls processes may make.
+//!
+//! `DriverPermissions` decide which drivers an app may use at all. A
+//! `SyscallFilter` goes further: the main loop asks the platform's filter
+//! about every `subscribe`, `command` and `allow` a process makes before
+//! passing it to the driver, and the filter can let it through, return
+//! `EPERM` to the app, or fault the app.
+
+use process::{Process, NUM_PROCS};
+use scheduler::Clock;
+
+/// The system calls a filter is asked about.
+#[derive(Copy,Clone,Debug,PartialEq,Eq)]
+pub enum SyscallKind {
+    Subscribe,
+    Command,
+    Allow
+}
+
+/// What to do with a system call.
+#[derive(Copy,Clone,Debug,PartialEq,Eq)]
+pub enum Verdict {
+    /// Pass the system call to the driver.
+    Allow,
+    /// Return `EPERM` to the app without calling the driver.
+    Deny,
+    /// Fault the app, as if it had crashed.
+    Fault
+}
+
+pub trait SyscallFilter {
+    /// Decides what to do with the process at `idx` calling `kind` with
+    /// driver number `driver_num` and minor number `minor_num`.
+    fn check(&mut self, idx: usize, process: &Process, kind: SyscallKind,
+             driver_num: usize, minor_num: usize) -> Verdict;
+
+    /// The number of system calls by the process at `idx` the filter has
+    /// denied or faulted it for.
+    fn violations(&self, _idx: usize) -> usize {
+        0
+    }
+}
+
+/// Lets every system call through.
+pub struct AllowAll;
+
+impl SyscallFilter for AllowAll {
+    fn check(&mut self, _: usize, _: &Process, _: SyscallKind, _: usize,
+             _: usize) -> Verdict {
+        Verdict::Allow
+    }
+}
+
+/// Allows or denies the system calls to a driver that match it.
+#[derive(Copy,Clone)]
+pub struct Rule {
+    /// The kind of system call matched, or `None` for all of them.
+    pub kind: Option<SyscallKind>,
+    pub driver_num: usize,
+    /// The minor number matched, or `None` for all of them.
+    pub minor_num: Option<usize>,
+    pub allow: bool
+}
+
+impl Rule {
+    fn matches(&self, kind: SyscallKind, driver_num: usize,
+               minor_num: usize) -> bool {
+        self.kind.map_or(true, |k| k == kind) &&
+            self.driver_num == driver_num &&
+            self.minor_num.map_or(true, |m| m == minor_num)
+    }
+}
+
+/// Most rate limits in one `Policy`. Any more are ignored.
+pub const MAX_RATE_LIMITS: usize = 4;
+
+/// Lets an app make at most `max` of the matching commands every `period_ms`
+/// milliseconds. Commands past that are violations.
+#[derive(Copy,Clone)]
+pub struct RateLimit {
+    pub driver_num: usize,
+    /// The command number matched, or `None` for all of them.
+    pub minor_num: Option<usize>,
+    pub max: usize,
+    pub period_ms: u32
+}
+
+/// The system calls one app may make.
+pub struct Policy {
+    /// Checked in order, and the first that matches decides. System calls
+    /// that match none are allowed if `allow_by_default` is set.
+    pub rules: &'static [Rule],
+    pub allow_by_default: bool,
+    pub rate_limits: &'static [RateLimit],
+    /// Fault the app on a violation instead of returning `EPERM`.
+    pub fault_on_violation: bool
+}
+
+/// A policy allowing every system call, for apps that come before ones with
+/// a policy in flash.
+pub const UNRESTRICTED: Policy = Policy {
+    rules: &[],
+    allow_by_default: true,
+    rate_limits: &[],
+    fault_on_violation: false
+};
+
+/// Commands counted towards a rate limit in its current period. Times are in
+/// clock ticks.
+#[derive(Copy,Clone)]
+struct Window {
+    start: u32,
+    count: usize
+}
+
+const NO_WINDOW: Window = Window { start: 0, count: 0 };
+
+/// Filters system calls by a `Policy` for each app, indexed by the app's
+/// position in flash like the platform's other per-app tables. Apps past the
+/// end of the policies may make any system call.
+///
+/// Apps are not matched by the name in their header, since an app could pick
+/// another app's name to escape its own policy.
+pub struct PolicyFilter<'a> {
+    clock: &'a Clock,
+    policies: &'a [Policy],
+    violations: [usize; NUM_PROCS],
+    windows: [[Window; MAX_RATE_LIMITS]; NUM_PROCS]
+}
+
+const NO_POLICIES: &'static [Policy] = &[];
+
+impl<'a> PolicyFilter<'a> {
+    pub const fn new(clock: &'a Clock) -> PolicyFilter<'a> {
+        PolicyFilter {
+            clock: clock,
+            policies: NO_POLICIES,
+            violations: [0; NUM_PROCS],
+            windows: [[NO_WINDOW; MAX_RATE_LIMITS]; NUM_PROCS]
+        }
+    }
+
+    /// Replaces the policies, usually from the platform's `init()`.
+    pub fn set_policies(&mut self, policies: &'a [Policy]) {
+        self.policies = policies;
+    }
+
+    fn policy_for(&self, process: &Process) -> Option<&'a Policy> {
+        let policies: &'a [Policy] = self.policies;
+        policies.get(process.app_num)
+    }
+
+    /// Counts a command against the rate limits it matches, returning whether
+    /// it is within all of them.
+    fn within_rate_limits(&mut self, idx: usize, policy: &Policy,
+                          driver_num: usize, minor_num: usize) -> bool {
+        let now = self.clock.now();
+        let frequency = self.clock.frequency() as u64;
+        let mut within = true;
+        for (limit, window) in policy.rate_limits.iter()
+                                     .zip(self.windows[idx].iter_mut()) {
+            let matches = limit.driver_num == driver_num &&
+                limit.minor_num.map_or(true, |m| m == minor_num);
+            if !matches {
+                continue;
+            }
+            let period = (limit.period_ms as u64 * frequency / 1000) as u32;
+            if now.wrapping_sub(window.start) >= period {
+                window.start = now;
+                window.count = 0;
+            }
+            if window.count < limit.max {
+                window.count += 1;
+            } else {
+                within = false;
+            }
+        }
+        within
+    }
+}
+
+impl<'a> SyscallFilter for PolicyFilter<'a> {
+    fn check(&mut self, idx: usize, process: &Process, kind: SyscallKind,
+             driver_num: usize, minor_num: usize) -> Verdict {
+        let policy = match self.policy_for(process) {
+            Some(policy) if idx < NUM_PROCS => policy,
+            _ => return Verdict::Allow
+        };
+
+        let allowed = policy.rules.iter()
+            .find(|rule| rule.matches(kind, driver_num, minor_num))
+            .map_or(policy.allow_by_default, |rule| rule.allow);
+        let allowed = allowed && (kind != SyscallKind::Command ||
+            self.within_rate_limits(idx, policy, driver_num, minor_num));
+        if allowed {
+            return Verdict::Allow;
+        }
+
+        self.violations[idx] += 1;
+        if policy.fault_on_violation {
+            Verdict::Fault
+        } else {
+            Verdict::Deny
+        }
+    }
+
+    fn violations(&self, idx: usize) -> usize {
+        self.violations.get(idx).map_or(0, |&count| count)
+    }
+}
@@ -8,11 +8,17 @@ extern crate support;
 
 pub mod callback;
 pub mod container;
+pub mod filter;
 pub mod mem;
 pub mod process;
+pub mod scheduler;
 
 pub use callback::{AppId, Callback};
 pub use container::{Container};
 pub use mem::{AppSlice, AppPtr, Private, Shared};
-pub use process::{Process,State, NUM_PROCS};
+pub use process::{Process, State, FaultResponse, DriverPermissions};
+pub use process::{MemoryRegion, NUM_PROCS, RestartPolicy};
+pub use process::Statistics;
+pub use filter::SyscallFilter;
+pub use scheduler::Scheduler;
 
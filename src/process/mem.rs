@@ -47,12 +47,10 @@ impl<L, T> DerefMut for AppPtr<L, T> {
 impl<L, T> Drop for AppPtr<L, T> {
     fn drop(&mut self) {
         unsafe {
-            let ps = &mut process::PROCS;
-            if ps.len() < self.process.idx() {
-                ps[self.process.idx()].as_mut().map(|process| 
-                    process.free(self.ptr.get_mut())
-                );
-            }
+            // If the process died its grant memory is already gone
+            process::lookup(self.process).map(|process|
+                process.free(self.ptr.get_mut())
+            );
         }
     }
 }
@@ -71,17 +69,35 @@ impl<L, T> AppSlice<L, T> {
         }
     }
 
+    /// The length of the slice, or 0 if the process that shared it has died.
     pub fn len(&self) -> usize {
-        self.len
+        if self.ptr.process.is_live() {
+            self.len
+        } else {
+            0
+        }
+    }
+
+    /// Whether the slice still lies below its process's memory break, i.e.
+    /// none of it has become kernel memory since it was shared.
+    pub fn is_app_memory(&self) -> bool {
+        let start = unsafe { self.ptr.ptr.get() as *const T as *const u8 };
+        let size = self.len * mem::size_of::<T>();
+        process::lookup(self.ptr.process).map_or(false, |process| {
+            process.check_exposed_bounds(start, size).is_ok()
+        })
     }
 }
 
+// Once the process that shared a slice dies its memory may belong to someone
+// else, so the slice appears empty.
+
 impl<L, T> AsRef<[T]> for AppSlice<L, T> {
     fn as_ref(&self) -> &[T] {
         unsafe {
             mem::transmute(Slice{
                 data: self.ptr.ptr.get(),
-                len: self.len
+                len: self.len()
             })
         }
     }
@@ -92,7 +108,7 @@ impl<L, T> AsMut<[T]> for AppSlice<L, T> {
         unsafe {
             mem::transmute(Slice{
                 data: self.ptr.ptr.get(),
-                len: self.len
+                len: self.len()
             })
         }
     }
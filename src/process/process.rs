@@ -1,59 +1,317 @@
-use core::intrinsics::{breakpoint, volatile_load, volatile_store};
-use core::{mem,ptr,intrinsics};
+use core::intrinsics::{volatile_load, volatile_store};
+use core::{cmp,fmt,mem,ptr,intrinsics,str};
 use core::raw::{Repr,Slice};
 
 use common::{RingBuffer, Queue};
 
 use container;
+use scheduler::Clock;
 
 #[no_mangle]
 pub static mut SYSCALL_FIRED : usize = 0;
 
+#[no_mangle]
+pub static mut APP_FAULT : usize = 0;
+
+/// The stack limit and the end of the accessible memory of the process being
+/// switched to. The context switch only saves registers on the process's
+/// stack if they fit between the two.
+#[no_mangle]
+pub static mut PROCESS_STACK_BOUNDS : [usize; 2] = [0, 0];
+
 #[allow(improper_ctypes)]
 extern {
     pub fn switch_to_user(user_stack: *const u8, mem_base: *const u8) -> *mut u8;
 }
 
-/// Size of each processes's memory region in bytes
-pub const PROC_MEMORY_SIZE : usize = 8192;
-pub const NUM_PROCS : usize = 2;
+/// Size of the pool that process memory regions are carved out of.
+pub const APP_MEMORY_SIZE : usize = 49152;
+/// Maximum number of processes.
+pub const NUM_PROCS : usize = 4;
+
+/// Memory region size for apps whose header doesn't request a RAM size.
+const DEFAULT_PROC_MEMORY_SIZE : usize = 8192;
+
+/// Smallest process memory region. Regions this size and larger are split
+/// into eight subregions by the MPU.
+const MIN_PROC_MEMORY_SIZE : usize = 512;
+
+/// Stack size for apps whose header doesn't request one.
+const DEFAULT_STACK_SIZE : usize = 512;
+
+/// Size of the inaccessible region below each process's stack. This is the
+/// smallest region the MPU supports.
+pub const STACK_GUARD_SIZE : usize = 32;
+
+/// Number of callbacks a process can have queued if its header doesn't ask
+/// for a different number.
+const DEFAULT_CALLBACK_QUEUE_LEN : usize = 10;
+
+/// Alignment of grant allocations and their headers.
+const GRANT_ALIGN : usize = 8;
+
+/// First word of a `TbfHeader`, "TOCK" in ASCII. Images without a header start
+/// with `LoadInfo` instead, whose first word (the size of the relocation data)
+/// is never this large.
+const TBF_MAGIC : usize = 0x4B434F54;
 
-static mut FREE_MEMORY_IDX: usize = 0;
+/// The `TbfHeader` version this kernel understands.
+const TBF_VERSION : usize = 1;
+
+/// `TbfHeader::flags` bit set for apps the kernel should load.
+const TBF_FLAG_ENABLED : usize = 1 << 0;
+
+/// Length of the app name in a `TbfHeader`.
+const TBF_NAME_LEN : usize = 16;
+
+/// Words of `LoadInfo` in images without a TBF header, which predate the
+/// fields after `bss_end_offset`.
+const LEGACY_LOAD_INFO_WORDS : usize = 8;
+
+/// `GrantBlock::owner` of a block that has been freed.
+const FREE_BLOCK : usize = !0;
+
+/// Offset of the first unused byte in `APP_MEMORY`.
+static mut APP_MEMORY_BREAK: usize = 0;
 
 #[link_section = ".app_memory"]
-static mut MEMORIES: [[u8; PROC_MEMORY_SIZE]; NUM_PROCS] = [[0; PROC_MEMORY_SIZE]; NUM_PROCS];
+static mut APP_MEMORY: [u8; APP_MEMORY_SIZE] = [0; APP_MEMORY_SIZE];
 
-pub static mut PROCS : [Option<Process<'static>>; NUM_PROCS] = [None, None];
+/// Regions of `APP_MEMORY` given back by terminated processes, below
+/// `APP_MEMORY_BREAK`.
+static mut FREE_APP_MEMORY: [Option<Slice<u8>>; NUM_PROCS] =
+    [None, None, None, None];
 
-pub fn schedule(callback: Callback, appid: ::AppId) -> bool {
+pub static mut PROCS : [Option<Process<'static>>; NUM_PROCS] =
+    [None, None, None, None];
+
+/// The process `appid` refers to, unless its grants and callbacks have since
+/// been thrown away because it faulted, restarted or was terminated. Stopping
+/// a process doesn't invalidate its `AppId`s. Terminated processes have no
+/// memory, so are never found.
+pub fn lookup(appid: ::AppId) -> Option<&'static mut Process<'static>> {
     let procs = unsafe { &mut PROCS };
-    let idx = appid.idx();
-    if idx >= procs.len() {
-        return false
-    }
+    procs.get_mut(appid.idx()).and_then(|p| p.as_mut()).and_then(|p| {
+        if p.generation == appid.generation() &&
+                p.state != State::Terminated {
+            Some(p)
+        } else {
+            None
+        }
+    })
+}
 
-    match procs[idx] {
+pub fn schedule(callback: Callback, appid: ::AppId) -> bool {
+    match lookup(appid) {
         None => false,
-        Some(ref mut p) => {
-            // TODO(alevy): validate appid liveness
-            p.callbacks.enqueue(callback)
+        Some(p) => {
+            let queued = p.callbacks.enqueue(callback);
+            if queued {
+                p.stats.callbacks_queued += 1;
+            } else {
+                p.stats.callbacks_dropped += 1;
+                p.unreported_drops += 1;
+            }
+            queued
         }
     }
 }
 
+/// Number of system call types, numbered from 0 by their SVC number.
+pub const NUM_SYSCALLS : usize = 6;
+
+/// Counters the kernel keeps for each process. They add up across restarts.
+#[derive(Copy,Clone,Default)]
+pub struct Statistics {
+    /// Timeslices in which the process ran.
+    pub timeslices: usize,
+    /// Times the process was scheduled and used up its whole timeslice.
+    pub timeslice_expirations: usize,
+    /// System calls made, indexed by SVC number.
+    pub syscalls: [usize; NUM_SYSCALLS],
+    /// Callbacks queued for the process.
+    pub callbacks_queued: usize,
+    /// Callbacks dropped because the process's callback queue was full.
+    pub callbacks_dropped: usize,
+    /// System calls refused because the process may not use the driver.
+    /// Those the syscall filter refuses are counted by the filter.
+    pub permission_denials: usize
+}
+
+/// The counters of the process in slot `idx`.
+pub fn statistics(idx: usize) -> Option<Statistics> {
+    unsafe {
+        PROCS.get(idx).and_then(|p| p.as_ref()).map(|p| p.stats)
+    }
+}
+
 #[derive(Copy,Clone,PartialEq,Eq)]
 pub enum Error {
     NoSuchApp,
     OutOfMemory,
-    AddressOutOfBounds
+    AddressOutOfBounds,
+    /// The address range overlaps memory owned by the kernel.
+    KernelMemory,
+    /// The address range wraps around the end of the address space.
+    AddressOverflow,
+    /// The memory is still exposed to a driver.
+    InUse
 }
 
-#[derive(Copy,Clone,PartialEq,Eq)]
+/// Why an app in flash was not loaded.
+#[derive(Copy,Clone,Debug,PartialEq,Eq)]
+pub enum LoadError {
+    /// The app needs a `needed` byte memory region, but there is no suitably
+    /// aligned space that large left in the app memory pool.
+    NotEnoughMemory { needed: usize, available: usize },
+    /// All `NUM_PROCS` process slots are already in use.
+    TooManyProcesses,
+    /// The image's length word is too small, unaligned or runs past the end of
+    /// flash. No apps after it are loaded, since there is no telling where the
+    /// next one starts.
+    BadLength { length: usize },
+    /// The header points at a part of the image that lies outside the image.
+    OutsideImage(ImagePart),
+    /// Loading the image would write a part of it outside the memory the
+    /// process can use.
+    OutsideMemory(ImagePart),
+    /// The part of the image must be word aligned but isn't.
+    Misaligned(ImagePart),
+    /// The TBF header's size is too small, unaligned, or leaves no room for
+    /// the `LoadInfo` after it.
+    BadHeader,
+    /// The TBF header is a version this kernel doesn't understand.
+    UnsupportedVersion { version: usize },
+    /// The image doesn't match the checksum in its TBF header.
+    BadChecksum { expected: usize, actual: usize },
+    /// The RAM or callback queue the header asks for could never fit in the
+    /// app memory pool.
+    TooLarge { min_app_ram_size: usize, callback_queue_len: usize }
+}
+
+/// The parts of an app image `LoadError`s refer to.
+#[derive(Copy,Clone,Debug,PartialEq,Eq)]
+pub enum ImagePart {
+    Relocations,
+    Text,
+    EntryPoint,
+    InitData,
+    Got,
+    Bss,
+    Stack
+}
+
+#[derive(Copy,Clone,Debug,PartialEq,Eq)]
 pub enum State {
+    /// Loaded, but not run yet. `start` queues the call to its entry point.
+    Unstarted,
     Running,
-    Waiting
+    Waiting,
+    /// Stopped by the kernel while `Running` or `Waiting`. `resume` returns
+    /// the process to the state it was stopped in.
+    Stopped,
+    StoppedWaiting,
+    Faulted,
+    /// Exited, or killed by the kernel. The process doesn't run again unless
+    /// it is restarted.
+    Terminated
+}
+
+impl State {
+    /// Whether a process in this state may move to `next`.
+    pub fn can_become(self, next: State) -> bool {
+        match (self, next) {
+            // Restarting reloads the process, which leaves it unstarted, and
+            // any process can be killed
+            (_, State::Unstarted) | (_, State::Terminated) => true,
+            (State::Unstarted, State::Waiting) => true,
+            (State::Running, State::Waiting) => true,
+            (State::Waiting, State::Running) => true,
+            (State::Running, State::Stopped) => true,
+            (State::Waiting, State::StoppedWaiting) => true,
+            (State::Stopped, State::Running) => true,
+            (State::StoppedWaiting, State::Waiting) => true,
+            (State::Running, State::Faulted) => true,
+            _ => false
+        }
+    }
+}
+
+/// What the kernel does with a process after it faults.
+#[derive(Copy,Clone,PartialEq,Eq)]
+pub enum FaultResponse {
+    /// Bring down the whole kernel.
+    Panic,
+    /// Reload the process from its TBF image and run it again from the start,
+    /// as often and as soon as the policy allows.
+    Restart(RestartPolicy),
+    /// Leave the process in the `Faulted` state and keep running the others.
+    Stop
 }
 
+/// Limits on restarting a process that keeps faulting.
+#[derive(Copy,Clone,PartialEq,Eq)]
+pub struct RestartPolicy {
+    /// Restarts after which a process that faults again is left faulted. 0
+    /// means no limit.
+    pub max_restarts: usize,
+    /// Milliseconds to wait before the first restart. Each restart after
+    /// that waits twice as long as the one before, up to `max_backoff_ms`.
+    pub backoff_ms: u32,
+    pub max_backoff_ms: u32
+}
+
+impl RestartPolicy {
+    /// Restarts straight away, however often the process faults.
+    pub const fn immediate() -> RestartPolicy {
+        RestartPolicy::new(0, 0, 0)
+    }
+
+    pub const fn new(max_restarts: usize, backoff_ms: u32,
+                     max_backoff_ms: u32) -> RestartPolicy {
+        RestartPolicy {
+            max_restarts: max_restarts,
+            backoff_ms: backoff_ms,
+            max_backoff_ms: max_backoff_ms
+        }
+    }
+
+    /// Whether a process restarted `restarts` times may restart again.
+    fn allows(&self, restarts: usize) -> bool {
+        self.max_restarts == 0 || restarts < self.max_restarts
+    }
+
+    /// Milliseconds to wait before restarting a process that has already
+    /// been restarted `restarts` times.
+    fn backoff_ms(&self, restarts: usize) -> u32 {
+        let backoff = (self.backoff_ms as u64) << cmp::min(restarts, 32);
+        cmp::min(backoff, self.max_backoff_ms as u64) as u32
+    }
+}
+
+/// The driver numbers a process may use through `subscribe`, `command` and
+/// `allow`, as a bitmask with bit `n` set if driver `n` is allowed.
+#[derive(Copy,Clone,PartialEq,Eq)]
+pub struct DriverPermissions {
+    allowed: u32
+}
+
+impl DriverPermissions {
+    /// Every driver, including any numbered above 31.
+    pub const fn all() -> DriverPermissions {
+        DriverPermissions { allowed: !0 }
+    }
+
+    /// Only the drivers whose bits are set in `allowed`.
+    pub const fn new(allowed: u32) -> DriverPermissions {
+        DriverPermissions { allowed: allowed }
+    }
+
+    pub fn permits(&self, driver_num: usize) -> bool {
+        self.allowed == !0 ||
+            (driver_num < 32 && self.allowed & (1 << driver_num) != 0)
+    }
+}
 
 #[derive(Copy,Clone,Debug)]
 pub struct Callback {
@@ -64,6 +322,22 @@ pub struct Callback {
     pub pc: usize
 }
 
+/// Header at the start of app images, between the length word and
+/// `LoadInfo`. The sizes of memory the app requests are in `LoadInfo`; the
+/// heap sizes here are already included in `LoadInfo::min_app_ram_size`.
+#[repr(C,packed)]
+struct TbfHeader {
+    magic: usize,             /* TBF_MAGIC */
+    version: usize,           /* Header format version */
+    header_size: usize,       /* Size of this header, LoadInfo follows it */
+    flags: usize,             /* TBF_FLAG_* */
+    checksum: usize,          /* XOR of the image's words after the header */
+    app_heap_size: usize,     /* Heap requested by the app */
+    kernel_heap_size: usize,  /* Grant memory requested for the app */
+    name: [u8; TBF_NAME_LEN]  /* App name, padded with NULs */
+}
+
+#[derive(Copy,Clone)]
 #[repr(C,packed)]
 struct LoadInfo {
     rel_data_size: usize,
@@ -73,7 +347,31 @@ struct LoadInfo {
     got_start_offset: usize,  /* Offset to start of GOT */
     got_end_offset: usize,    /* Offset to end of GOT */
     bss_start_offset: usize,  /* Offset to start of BSS */
-    bss_end_offset: usize    /* Offset to end of BSS */
+    bss_end_offset: usize,   /* Offset to end of BSS */
+    min_app_ram_size: usize, /* RAM requested by the app, 0 for default */
+    stack_size: usize,       /* Stack requested by the app, 0 for default */
+    priority: usize,         /* Scheduling priority, higher runs first */
+    period_us: usize,        /* Period for deadline scheduling, 0 if none */
+    budget_us: usize,        /* CPU time the app may use each period */
+    callback_queue_len: usize /* Callback queue length, 0 for default */
+}
+
+impl LoadInfo {
+    fn stack_size(&self) -> usize {
+        if self.stack_size == 0 {
+            DEFAULT_STACK_SIZE
+        } else {
+            self.stack_size
+        }
+    }
+
+    fn callback_queue_len(&self) -> usize {
+        if self.callback_queue_len == 0 {
+            DEFAULT_CALLBACK_QUEUE_LEN
+        } else {
+            self.callback_queue_len
+        }
+    }
 }
 
 pub struct Process<'a> {
@@ -83,37 +381,565 @@ pub struct Process<'a> {
     app_memory_break: *const u8,
     kernel_memory_break: *const u8,
 
+    /// End of the highest buffer the app has exposed to drivers. Drivers may
+    /// keep those buffers indefinitely, so the break can't be lowered below
+    /// this and the memory handed out as grants.
+    exposed_end: *const u8,
+
+    /// Top of the grant allocations, just below the callback queue. Grant
+    /// blocks fill the memory from `kernel_memory_break` up to here.
+    grant_end: *const u8,
+
     /// Process text segment
     text: Slice<u8>,
 
+    /// The app's name from its TBF header, padded with NULs. Empty for images
+    /// without a header.
+    name: [u8; TBF_NAME_LEN],
+
+    /// Start of the app's code in `text`, past the load info and relocation
+    /// data. Offsets from here match addresses in the app's ELF.
+    text_start: *const u8,
+
     /// The offset in `memory` to use for the process stack.
     cur_stack: *const u8,
 
+    /// Start of the guard region directly below the process stack. The MPU
+    /// denies the process access to it, so a stack overflow faults instead of
+    /// running into data and BSS.
+    stack_guard: *const u8,
+
     wait_pc: usize,
     psr: usize,
 
-    pub state: State,
+    /// The call into the app's entry point that `start` queues.
+    init_callback: Callback,
+
+    state: State,
 
-    pub callbacks: RingBuffer<'a, Callback>
+    /// The app's position in flash, counting apps that weren't loaded. The
+    /// platform's per-app tables are indexed by it.
+    pub app_num: usize,
+
+    pub fault_response: FaultResponse,
+
+    /// The drivers the process may use.
+    pub driver_permissions: DriverPermissions,
+
+    /// Bumped whenever the process's grants and callbacks are thrown away,
+    /// invalidating `AppId`s handed out before.
+    generation: usize,
+
+    /// Scheduling priority from the app's header. Higher runs first.
+    priority: usize,
+
+    /// Period and per-period CPU budget, in microseconds, from the app's
+    /// header for deadline scheduling. A period of 0 means the app is not
+    /// periodic.
+    period_us: usize,
+    budget_us: usize,
+
+    pub callbacks: RingBuffer<'a, Callback>,
+
+    /// Length of `callbacks`, from the app's header.
+    callback_queue_len: usize,
+
+    /// Callbacks dropped because `callbacks` was full since the app last
+    /// asked with the dropped callbacks memop.
+    unreported_drops: usize,
+
+    /// Times the process was restarted after faulting, since it was last
+    /// loaded or restarted by hand.
+    restarts: usize,
+
+    /// When, in clock ticks, a faulted process is due to be restarted.
+    restart_at: Option<u32>,
+
+    pub stats: Statistics
 }
 
+/// Loads processes from flash starting at `start_addr`, up to `end_addr`.
+///
+/// Each app gets a power-of-two sized memory region, large enough for the RAM
+/// its header asks for, carved out of the `.app_memory` pool. As many apps are
+/// loaded as fit; `skipped` is called with the address of each app that isn't.
+/// Apps whose image doesn't check out against its header are skipped too,
+/// rather than letting the loader write outside the process's memory.
+///
+/// `fault_responses` is indexed by the order of apps in flash and decides what
+/// happens to each app when it faults. Apps without an entry are stopped.
+/// `driver_permissions` is indexed the same way and limits which drivers each
+/// app may use. Apps without an entry may use all of them.
 #[inline(never)]
-pub unsafe fn load_processes(mut start_addr: *const usize) ->
-        &'static mut [Option<Process<'static>>] {
-    for op in PROCS.iter_mut() {
-        if *start_addr != 0 {
-            let prog_start = start_addr.offset(1);
-            let length = *start_addr as isize;
-            start_addr = (start_addr as *const u8).offset(length) as *const usize;
-
-            *op = Process::create(prog_start, length);
-        } else {
-            *op = None;
+pub unsafe fn load_processes<F>(mut start_addr: *const usize,
+                                end_addr: *const usize,
+                                fault_responses: &[FaultResponse],
+                                driver_permissions: &[DriverPermissions],
+                                mut skipped: F) ->
+        &'static mut [Option<Process<'static>>]
+        where F: FnMut(*const u8, LoadError) {
+    let mut app_num = 0;
+    let mut proc_idx = 0;
+    // Apps end at a zero length word, or at erased flash
+    while start_addr < end_addr && *start_addr != 0 && *start_addr != !0 {
+        let app_start = start_addr as *const u8;
+        let prog_start = start_addr.offset(1);
+        let length = *start_addr;
+        if !length_is_valid(app_start, length, end_addr as *const u8) {
+            skipped(app_start, LoadError::BadLength { length: length });
+            break;
+        }
+        start_addr = app_start.offset(length as isize) as *const usize;
+
+        let fault_response = fault_responses.get(app_num).map(|r| *r)
+                                            .unwrap_or(FaultResponse::Stop);
+        let permissions = driver_permissions.get(app_num).map(|p| *p)
+                                .unwrap_or(DriverPermissions::all());
+        let this_app = app_num;
+        app_num += 1;
+
+        if proc_idx >= PROCS.len() {
+            skipped(app_start, LoadError::TooManyProcesses);
+            continue;
+        }
+
+        if let Err(err) = check_header(app_start, length) {
+            skipped(app_start, err);
+            continue;
+        }
+        let load_info = load_info_of(app_start);
+        let header_len = image_header_len(app_start);
+        if let Err(err) = check_image(&load_info, header_len, length) {
+            skipped(app_start, err);
+            continue;
+        }
+        let needed = match memory_size_for(&load_info) {
+            Ok(needed) => needed,
+            Err(err) => {
+                skipped(app_start, err);
+                continue;
+            }
+        };
+        let overhead = kernel_memory_overhead(load_info.callback_queue_len());
+        let usable = subregion_floor(needed - overhead, needed);
+        let rel_data = app_start.offset(header_len as isize);
+        if let Err(err) = check_memory(&load_info, rel_data, usable) {
+            skipped(app_start, err);
+            continue;
+        }
+        match alloc_app_memory(needed) {
+            Some(memory) => {
+                let mut process = Process::create(prog_start, length as isize,
+                                                  memory);
+                process.app_num = this_app;
+                process.fault_response = fault_response;
+                process.driver_permissions = permissions;
+                // Disabled apps stay loaded but unstarted
+                if is_enabled(app_start) {
+                    process.start();
+                }
+                PROCS[proc_idx] = Some(process);
+                proc_idx += 1;
+            },
+            None => {
+                skipped(app_start, LoadError::NotEnoughMemory {
+                    needed: needed,
+                    available: APP_MEMORY_SIZE - APP_MEMORY_BREAK
+                });
+            }
         }
     }
     &mut PROCS
 }
 
+/// Whether an image `length` bytes long starting at `app_start` has room for
+/// its length word and header, is word aligned, and ends by `flash_end`.
+fn length_is_valid(app_start: *const u8, length: usize, flash_end: *const u8)
+        -> bool {
+    let word = mem::size_of::<usize>();
+    length >= word + LEGACY_LOAD_INFO_WORDS * word && length % word == 0 &&
+        end_within(app_start as usize, length, flash_end as usize).is_some()
+}
+
+/// `start + len`, if that doesn't overflow or pass `limit`.
+fn end_within(start: usize, len: usize, limit: usize) -> Option<usize> {
+    start.checked_add(len).and_then(|end| {
+        if end <= limit { Some(end) } else { None }
+    })
+}
+
+/// The TBF header of the image at `app_start`, if it has one.
+unsafe fn tbf_header(app_start: *const u8) -> Option<&'static TbfHeader> {
+    let header = (app_start as *const usize).offset(1);
+    if *header == TBF_MAGIC {
+        Some(mem::transmute(header))
+    } else {
+        None
+    }
+}
+
+/// The `LoadInfo` of the image at `app_start`, which follows the TBF header
+/// if there is one. Images without a header only have the first
+/// `LEGACY_LOAD_INFO_WORDS` words; the fields after those are left zero so
+/// their defaults are used.
+unsafe fn load_info_of(app_start: *const u8) -> LoadInfo {
+    let word = mem::size_of::<usize>();
+    match tbf_header(app_start) {
+        Some(header) => {
+            let start = app_start.offset((word + header.header_size) as isize);
+            *(start as *const LoadInfo)
+        },
+        None => {
+            let mut load_info: LoadInfo = mem::zeroed();
+            ptr::copy_nonoverlapping(app_start.offset(word as isize)
+                                         as *const usize,
+                                     &mut load_info as *mut LoadInfo
+                                         as *mut usize,
+                                     LEGACY_LOAD_INFO_WORDS);
+            load_info
+        }
+    }
+}
+
+/// Size of everything in the image at `app_start` before its relocation
+/// data: the length word, the TBF header if there is one and `LoadInfo`.
+unsafe fn image_header_len(app_start: *const u8) -> usize {
+    let word = mem::size_of::<usize>();
+    match tbf_header(app_start) {
+        Some(header) => word + header.header_size + mem::size_of::<LoadInfo>(),
+        None => word + LEGACY_LOAD_INFO_WORDS * word
+    }
+}
+
+/// Checks the TBF header of the `length` byte image at `app_start`, if it has
+/// one: that the kernel understands it and that the image matches its
+/// checksum. Images without a header pass. Disabled apps pass too; see
+/// `is_enabled`.
+unsafe fn check_header(app_start: *const u8, length: usize)
+        -> Result<(), LoadError> {
+    let header = match tbf_header(app_start) {
+        Some(header) => header,
+        None => return Ok(())
+    };
+    let word = mem::size_of::<usize>();
+    if length < word + mem::size_of::<TbfHeader>() {
+        return Err(LoadError::BadHeader);
+    }
+    if header.version != TBF_VERSION {
+        return Err(LoadError::UnsupportedVersion { version: header.version });
+    }
+    let header_size = header.header_size;
+    if header_size < mem::size_of::<TbfHeader>() || header_size % word != 0 ||
+            end_within(word + header_size, mem::size_of::<LoadInfo>(),
+                       length).is_none() {
+        return Err(LoadError::BadHeader);
+    }
+
+    let body : &[usize] = mem::transmute(Slice {
+        data: app_start.offset((word + header_size) as isize) as *const usize,
+        len: (length - word - header_size) / word
+    });
+    let checksum = body.iter().fold(0, |checksum, w| checksum ^ *w);
+    if checksum != header.checksum {
+        return Err(LoadError::BadChecksum {
+            expected: header.checksum,
+            actual: checksum
+        });
+    }
+    Ok(())
+}
+
+/// Whether the image at `app_start` should start running once loaded. Images
+/// without a TBF header always do.
+unsafe fn is_enabled(app_start: *const u8) -> bool {
+    tbf_header(app_start).map_or(true, |header| {
+        header.flags & TBF_FLAG_ENABLED != 0
+    })
+}
+
+/// Checks that the relocations, text, entry point and initial data
+/// `load_info` points at lie within the `length` byte image it heads.
+/// `header_len` is the size of everything up to the end of `load_info`.
+fn check_image(load_info: &LoadInfo, header_len: usize, length: usize)
+        -> Result<(), LoadError> {
+    if load_info.rel_data_size % mem::size_of::<usize>() != 0 {
+        return Err(LoadError::Misaligned(ImagePart::Relocations));
+    }
+    let text_start = try!(
+        end_within(header_len, load_info.rel_data_size, length)
+            .ok_or(LoadError::OutsideImage(ImagePart::Relocations)));
+    let text_end = try!(
+        end_within(text_start, load_info.init_data_loc, length)
+            .ok_or(LoadError::OutsideImage(ImagePart::Text)));
+    try!(end_within(text_end, load_info.init_data_size, length)
+            .ok_or(LoadError::OutsideImage(ImagePart::InitData)));
+    if load_info.entry_loc >= load_info.init_data_loc {
+        return Err(LoadError::OutsideImage(ImagePart::EntryPoint));
+    }
+    Ok(())
+}
+
+/// Checks that everything `load` writes for the app `load_info` describes
+/// (its initial data, GOT, BSS, relocated words and stack) fits in the first
+/// `usable` bytes of the process's memory. Its relocation data starts at
+/// `rel_data`.
+unsafe fn check_memory(load_info: &LoadInfo, rel_data: *const u8,
+                       usable: usize) -> Result<(), LoadError> {
+    let word = mem::size_of::<usize>();
+    if load_info.init_data_size > usable {
+        return Err(LoadError::OutsideMemory(ImagePart::InitData));
+    }
+    if load_info.got_start_offset > load_info.got_end_offset ||
+            load_info.got_end_offset > usable {
+        return Err(LoadError::OutsideMemory(ImagePart::Got));
+    }
+    if load_info.got_start_offset % word != 0 ||
+            load_info.got_end_offset % word != 0 {
+        return Err(LoadError::Misaligned(ImagePart::Got));
+    }
+    if load_info.bss_start_offset > load_info.bss_end_offset ||
+            load_info.bss_end_offset > usable {
+        return Err(LoadError::OutsideMemory(ImagePart::Bss));
+    }
+
+    let rel_data : &[usize] = mem::transmute(Slice {
+        data: rel_data as *const usize,
+        len: load_info.rel_data_size / word
+    });
+    for (i, addr) in rel_data.iter().enumerate() {
+        if i % 2 == 0 && end_within(*addr, word, usable).is_none() {
+            return Err(LoadError::OutsideMemory(ImagePart::Relocations));
+        }
+    }
+
+    // Laid out the way `load_image` does it
+    let app_mem_start = (load_info.bss_end_offset + 7) & !7;
+    let guard_mask = STACK_GUARD_SIZE - 1;
+    let stack_guard = (app_mem_start + guard_mask) & !guard_mask;
+    if end_within(stack_guard, STACK_GUARD_SIZE + load_info.stack_size(),
+                  usable).is_none() {
+        return Err(LoadError::OutsideMemory(ImagePart::Stack));
+    }
+    Ok(())
+}
+
+/// Size of the kernel-owned area at the top of each process's memory: the
+/// container pointers and a callback queue `callback_queue_len` long, plus
+/// padding to align the grant blocks below them.
+unsafe fn kernel_memory_overhead(callback_queue_len: usize) -> usize {
+    let num_ctrs = volatile_load(&container::CONTAINER_COUNTER);
+    num_ctrs * mem::size_of::<*const usize>() +
+        (callback_queue_len + 1) * mem::size_of::<Option<Callback>>() +
+        GRANT_ALIGN - 1
+}
+
+/// Header in front of every grant allocation.
+#[repr(C)]
+struct GrantBlock {
+    /// Size of the block's data, not counting the header.
+    size: usize,
+    /// Number of the container the block belongs to, or `FREE_BLOCK`.
+    owner: usize
+}
+
+fn grant_align(size: usize) -> usize {
+    (size + GRANT_ALIGN - 1) & !(GRANT_ALIGN - 1)
+}
+
+unsafe fn grant_data(block: *mut GrantBlock) -> *mut u8 {
+    (block as *mut u8).offset(mem::size_of::<GrantBlock>() as isize)
+}
+
+/// Walks the grant blocks of a process from the lowest address up.
+struct GrantBlocks {
+    next: *mut GrantBlock,
+    end: *const u8
+}
+
+impl Iterator for GrantBlocks {
+    type Item = *mut GrantBlock;
+
+    fn next(&mut self) -> Option<*mut GrantBlock> {
+        if self.next as *const u8 >= self.end {
+            None
+        } else {
+            let block = self.next;
+            unsafe {
+                self.next = grant_data(block).offset((*block).size as isize)
+                                as *mut GrantBlock;
+            }
+            Some(block)
+        }
+    }
+}
+
+/// The memory region size, a power of two, for the app described by
+/// `load_info`. Fails if no region that size fits in the app memory pool.
+unsafe fn memory_size_for(load_info: &LoadInfo) -> Result<usize, LoadError> {
+    let requested = load_info.min_app_ram_size;
+    let callback_queue_len = load_info.callback_queue_len();
+    let too_large = LoadError::TooLarge {
+        min_app_ram_size: requested,
+        callback_queue_len: callback_queue_len
+    };
+    // Bound both before doing any arithmetic with them
+    let callback_bytes = callback_queue_len.checked_add(1).and_then(|len| {
+        len.checked_mul(mem::size_of::<Option<Callback>>())
+    });
+    if requested > APP_MEMORY_SIZE ||
+            callback_bytes.map_or(true, |bytes| bytes > APP_MEMORY_SIZE) {
+        return Err(too_large);
+    }
+
+    let overhead = kernel_memory_overhead(callback_queue_len);
+    let mut size = if requested == 0 {
+        DEFAULT_PROC_MEMORY_SIZE
+    } else {
+        cmp::max((requested + overhead).next_power_of_two(),
+                 MIN_PROC_MEMORY_SIZE)
+    };
+    // The app can only use the subregions below the kernel-owned memory, so
+    // make sure those alone are big enough.
+    while size < overhead ||
+            subregion_floor(size - overhead, size) < requested {
+        size *= 2;
+        if size > APP_MEMORY_SIZE {
+            return Err(too_large);
+        }
+    }
+    Ok(size)
+}
+
+/// Rounds `offset` into a region of `size` bytes down to a subregion boundary.
+fn subregion_floor(offset: usize, size: usize) -> usize {
+    let subregion_size = size / 8;
+    offset - offset % subregion_size
+}
+
+/// A memory region as programmed into the MPU.
+#[derive(Copy,Clone)]
+pub struct MemoryRegion {
+    /// Base address, aligned to `size`.
+    pub start: usize,
+    /// Size in bytes, a power of two of at least 32.
+    pub size: usize,
+    /// Bit `i` disables the `i`th eighth of the region. Only regions of 256
+    /// bytes or more have subregions, so smaller regions leave this 0.
+    pub subregions_disabled: u8
+}
+
+impl MemoryRegion {
+    /// The smallest region that covers `start` to `end`, with subregions that
+    /// don't overlap that range disabled.
+    pub fn covering(start: usize, end: usize) -> MemoryRegion {
+        let mut size = cmp::max((end - start).next_power_of_two(), 32);
+        while start & !(size - 1) != (end - 1) & !(size - 1) {
+            size *= 2;
+        }
+        let base = start & !(size - 1);
+        let mut disabled = 0;
+        if size >= 256 {
+            let subregion_size = size / 8;
+            for i in 0..8 {
+                let sub_start = base + i * subregion_size;
+                if sub_start + subregion_size <= start || sub_start >= end {
+                    disabled |= 1 << i;
+                }
+            }
+        }
+        MemoryRegion {
+            start: base,
+            size: size,
+            subregions_disabled: disabled
+        }
+    }
+
+    /// Region size as encoded in the MPU, 2^(len + 1) bytes.
+    pub fn mpu_len(&self) -> u32 {
+        self.size.trailing_zeros() - 1
+    }
+
+    /// The addresses actually accessible through the region: from the first
+    /// to the last enabled subregion.
+    pub fn effective_range(&self) -> (usize, usize) {
+        if self.subregions_disabled == 0 {
+            return (self.start, self.start + self.size);
+        }
+        let subregion_size = self.size / 8;
+        let enabled = !self.subregions_disabled;
+        let first = enabled.trailing_zeros() as usize;
+        let last = 8 - enabled.leading_zeros() as usize;
+        (self.start + first * subregion_size, self.start + last * subregion_size)
+    }
+
+    pub fn contains(&self, addr: usize) -> bool {
+        let (start, end) = self.effective_range();
+        addr >= start && addr < end
+    }
+}
+
+impl fmt::Display for MemoryRegion {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        let (start, end) = self.effective_range();
+        write!(f, "0x{:08x}-0x{:08x} (MPU 0x{:08x} size {} subregions off 0b{:08b})",
+               start, end, self.start, self.size, self.subregions_disabled)
+    }
+}
+
+/// Carves a `size` byte region out of the unused part of the app memory pool.
+/// The region is aligned to its size, as the MPU requires.
+///
+/// The smallest large enough region a terminated process gave back is reused
+/// first, whole: it is a power of two aligned to its size too.
+unsafe fn alloc_app_memory(size: usize) -> Option<Slice<u8>> {
+    let mut best: Option<usize> = None;
+    for (i, slot) in FREE_APP_MEMORY.iter().enumerate() {
+        if let Some(region) = *slot {
+            let smaller = best.map_or(true, |b| {
+                FREE_APP_MEMORY[b].map_or(true, |r| region.len < r.len)
+            });
+            if region.len >= size && smaller {
+                best = Some(i);
+            }
+        }
+    }
+    if let Some(i) = best {
+        let region = FREE_APP_MEMORY[i];
+        FREE_APP_MEMORY[i] = None;
+        return region;
+    }
+
+    let pool_start = APP_MEMORY.as_ptr() as usize;
+    let pool_end = pool_start + APP_MEMORY_SIZE;
+    let free_start = pool_start + APP_MEMORY_BREAK;
+    let region_start = (free_start + size - 1) & !(size - 1);
+    if region_start + size > pool_end {
+        None
+    } else {
+        APP_MEMORY_BREAK = region_start + size - pool_start;
+        Some(Slice {
+            data: region_start as *const u8,
+            len: size
+        })
+    }
+}
+
+/// Gives a region from `alloc_app_memory` back to the app memory pool.
+unsafe fn free_app_memory(region: Slice<u8>) {
+    let offset = region.data as usize - APP_MEMORY.as_ptr() as usize;
+    if offset + region.len == APP_MEMORY_BREAK {
+        // The last region carved out, so the pool can simply shrink
+        APP_MEMORY_BREAK = offset;
+        return;
+    }
+    for slot in FREE_APP_MEMORY.iter_mut() {
+        if slot.is_none() {
+            *slot = Some(region);
+            return;
+        }
+    }
+}
+
 impl<'a> Process<'a> {
     pub const fn mem_start(&self) -> *const u8 {
         self.memory.data
@@ -125,82 +951,286 @@ impl<'a> Process<'a> {
         }
     }
 
-    pub fn memory_regions(&self) -> (usize, usize, usize, usize) {
-        let data_start = self.memory.data as usize;
-        let data_len = 12;
+    /// Start of the app's image in flash, from its length word.
+    pub fn flash_start(&self) -> *const u8 {
+        self.text.data
+    }
+
+    /// Size in bytes of the app's image in flash.
+    pub fn flash_size(&self) -> usize {
+        self.text.len
+    }
+
+    /// The lowest address the process's stack may grow down to, just above
+    /// the stack guard.
+    pub fn stack_limit(&self) -> *const u8 {
+        unsafe { self.stack_guard.offset(STACK_GUARD_SIZE as isize) }
+    }
+
+    /// The end of the memory the process may access. This is the kernel
+    /// memory break rounded down to an MPU subregion, since the subregion
+    /// holding the break also holds kernel-owned memory.
+    pub fn app_accessible_end(&self) -> *const u8 {
+        self.subregion_floor(self.kernel_memory_break)
+    }
+
+    fn subregion_floor(&self, addr: *const u8) -> *const u8 {
+        let offset = addr as usize - self.memory.data as usize;
+        unsafe {
+            self.memory.data.offset(
+                subregion_floor(offset, self.memory.len) as isize)
+        }
+    }
+
+    /// The MPU regions for the process: its data, its text and the guard
+    /// below its stack, in that order.
+    ///
+    /// The data region is the whole process memory with the subregions above
+    /// `app_accessible_end` disabled, so the process can't reach grants or the
+    /// callback queue. The text region covers exactly the app's image in flash.
+    pub fn memory_regions(&self) -> (MemoryRegion, MemoryRegion, MemoryRegion) {
+        let subregion_size = self.memory.len / 8;
+        let enabled = (self.app_accessible_end() as usize -
+                       self.memory.data as usize) / subregion_size;
+        let data = MemoryRegion {
+            start: self.memory.data as usize,
+            size: self.memory.len,
+            subregions_disabled: !((1u16 << enabled) - 1) as u8
+        };
 
         let text_start = self.text.data as usize;
-        let text_len = ((32 - self.text.len.leading_zeros()) - 2) as usize;
-        (data_start, data_len, text_start, text_len)
-    }
-
-    pub unsafe fn create(start_addr: *const usize, length: isize) -> Option<Process<'a>> {
-        let cur_idx = FREE_MEMORY_IDX;
-        if cur_idx <= MEMORIES.len() {
-            FREE_MEMORY_IDX += 1;
-            let memory = MEMORIES[cur_idx].repr();
-
-            let mut kernel_memory_break = {
-                // make room for container pointers
-                let psz = mem::size_of::<*const usize>();
-                let num_ctrs = volatile_load(&container::CONTAINER_COUNTER);
-                let container_ptrs_size = num_ctrs * psz;
-                let res = memory.data.offset((memory.len - container_ptrs_size) as isize);
-                // set all ptrs to null
-                let opts : &mut [*const usize] = mem::transmute(Slice {
-                    data: res as *mut *const usize,
-                    len: num_ctrs
-                });
-                for opt in opts.iter_mut() {
-                    *opt = ptr::null()
+        let text = MemoryRegion::covering(text_start, text_start + self.text.len);
+
+        let stack_guard = MemoryRegion {
+            start: self.stack_guard as usize,
+            size: STACK_GUARD_SIZE,
+            subregions_disabled: 0
+        };
+
+        (data, text, stack_guard)
+    }
+
+    pub unsafe fn create(start_addr: *const usize, length: isize,
+                         memory: Slice<u8>) -> Process<'a> {
+        let app_start = start_addr.offset(-1) as *const u8;
+        let load_info = load_info_of(app_start);
+        let name = tbf_header(app_start).map_or([0; TBF_NAME_LEN], |h| h.name);
+        let callback_queue_len = load_info.callback_queue_len();
+        let (kernel_memory_break, callbacks) =
+            init_kernel_memory(&memory, callback_queue_len);
+
+        let mut process = Process {
+            memory: memory,
+            app_memory_break: memory.data,
+            kernel_memory_break: kernel_memory_break,
+            exposed_end: memory.data,
+            grant_end: kernel_memory_break,
+            text: Slice {
+                data: app_start,
+                len: length as usize },
+            name: name,
+            text_start: start_addr as *const u8,
+            cur_stack: memory.data,
+            stack_guard: memory.data,
+            wait_pc: 0,
+            psr: 0x01000000,
+            init_callback: Callback { r0: 0, r1: 0, r2: 0, r3: 0, pc: 0 },
+            state: State::Unstarted,
+            app_num: 0,
+            fault_response: FaultResponse::Stop,
+            driver_permissions: DriverPermissions::all(),
+            generation: 0,
+            priority: 0,
+            period_us: 0,
+            budget_us: 0,
+            callbacks: callbacks,
+            callback_queue_len: callback_queue_len,
+            unreported_drops: 0,
+            restarts: 0,
+            restart_at: None,
+            stats: Default::default()
+        };
+        process.load_image();
+
+        process
+    }
+
+    /// Runs an unstarted process from its entry point. Does nothing to
+    /// processes that have already started.
+    pub unsafe fn start(&mut self) {
+        if self.state == State::Unstarted {
+            self.set_state(State::Waiting);
+            self.callbacks.enqueue(self.init_callback);
+        }
+    }
+
+    /// Loads the process image from flash into its memory, leaving the
+    /// process unstarted with the call into the app's entry point ready.
+    unsafe fn load_image(&mut self) {
+        let app_start = self.text.data;
+        let load_result = load(&load_info_of(app_start),
+                               app_start.offset(
+                                   image_header_len(app_start) as isize),
+                               self.memory.data);
+
+        let stack_size = load_result.stack_size;
+
+        // The guard must be aligned to its size for the MPU
+        let guard_mask = STACK_GUARD_SIZE - 1;
+        let stack_guard = ((load_result.app_mem_start as usize + guard_mask)
+                           & !guard_mask) as *const u8;
+        let stack_top =
+            stack_guard.offset((STACK_GUARD_SIZE + stack_size) as isize);
+
+        self.text_start = load_result.text_start;
+        self.priority = load_result.priority;
+        self.period_us = load_result.period_us;
+        self.budget_us = load_result.budget_us;
+        self.stack_guard = stack_guard;
+        self.app_memory_break = stack_top;
+        self.exposed_end = self.memory.data;
+        self.cur_stack = stack_top;
+        self.wait_pc = 0;
+        self.psr = 0x01000000;
+        self.set_state(State::Unstarted);
+
+        self.init_callback = Callback {
+            pc: load_result.init_fn,
+            r0: load_result.app_mem_start as usize,
+            r1: self.app_memory_break as usize,
+            r2: self.app_accessible_end() as usize,
+            r3: 0
+        };
+    }
+
+    /// Drops all pending callbacks and grant state of the process, handing
+    /// the kernel-owned region back to its freshly loaded layout.
+    unsafe fn free_kernel_memory(&mut self) {
+        let (kernel_memory_break, callbacks) =
+            init_kernel_memory(&self.memory, self.callback_queue_len);
+        self.kernel_memory_break = kernel_memory_break;
+        self.grant_end = kernel_memory_break;
+        self.callbacks = callbacks;
+        self.unreported_drops = 0;
+        self.generation = self.generation.wrapping_add(1);
+    }
+
+    /// Handles a fault in the process according to its `fault_response`.
+    /// Restarts are left to `restart_if_due`, once the back-off `clock`
+    /// measures has passed.
+    pub unsafe fn fault_state(&mut self, clock: &Clock) {
+        self.set_state(State::Faulted);
+        match self.fault_response {
+            FaultResponse::Panic => {
+                panic!("Process faulted. Stack: {:?}, memory: {:?}",
+                       self.cur_stack, self.memory.data);
+            },
+            FaultResponse::Restart(policy) => {
+                self.free_kernel_memory();
+                if policy.allows(self.restarts) {
+                    let backoff_ms = policy.backoff_ms(self.restarts);
+                    let ticks = backoff_ms as u64 * clock.frequency() as u64
+                        / 1000;
+                    self.restart_at =
+                        Some(clock.now().wrapping_add(ticks as u32));
                 }
-                res
-            };
-
-            // Take callback buffer from of memory
-            let callback_size = mem::size_of::<Option<Callback>>();
-            let callback_len = 10;
-            let callback_offset = callback_len * callback_size;
-            // Set kernel break to beginning of callback buffer
-            kernel_memory_break =
-                kernel_memory_break.offset(-(callback_offset as isize));
-            let callback_buf = mem::transmute(Slice {
-                data: kernel_memory_break as *const Option<Callback>,
-                len: callback_len
-            });
+            },
+            FaultResponse::Stop => {
+                self.free_kernel_memory();
+            }
+        }
+    }
 
-            let callbacks = RingBuffer::new(callback_buf);
-
-            let load_result = load(start_addr, memory.data);
-
-            let stack_bottom = load_result.app_mem_start.offset(512);
-
-            let mut process = Process {
-                memory: memory,
-                app_memory_break: stack_bottom,
-                kernel_memory_break: kernel_memory_break,
-                text: Slice {
-                    data: start_addr.offset(-1) as *const u8,
-                    len: length as usize },
-                cur_stack: stack_bottom,
-                wait_pc: 0,
-                psr: 0x01000000,
-                state: State::Waiting,
-                callbacks: callbacks
-            };
-
-            process.callbacks.enqueue(Callback {
-                pc: load_result.init_fn,
-                r0: load_result.app_mem_start as usize,
-                r1: process.app_memory_break as usize,
-                r2: process.kernel_memory_break as usize,
-                r3: 0
-            });
+    /// Restarts the process from flash if it faulted and its back-off has
+    /// passed.
+    pub unsafe fn restart_if_due(&mut self, clock: &Clock) {
+        match self.restart_at {
+            Some(at) if (clock.now().wrapping_sub(at) as i32) >= 0 => {
+                self.restart_at = None;
+                self.restarts += 1;
+                self.load_image();
+                self.start();
+            },
+            _ => {}
+        }
+    }
 
-            Some(process)
-        } else {
-            None
+    /// The clock time the process is due to be restarted at after faulting,
+    /// if it is waiting out its back-off.
+    pub fn restart_at(&self) -> Option<u32> {
+        self.restart_at
+    }
+
+    /// Times the process was restarted after faulting.
+    pub fn restarts(&self) -> usize {
+        self.restarts
+    }
+
+    /// Keeps the process from running until `resume` is called. Callbacks
+    /// for it are still queued in the meantime.
+    pub fn stop(&mut self) {
+        match self.state {
+            State::Running => self.set_state(State::Stopped),
+            State::Waiting => self.set_state(State::StoppedWaiting),
+            _ => {}
+        }
+    }
+
+    pub fn resume(&mut self) {
+        match self.state {
+            State::Stopped => self.set_state(State::Running),
+            State::StoppedWaiting => self.set_state(State::Waiting),
+            _ => {}
+        }
+    }
+
+    /// Throws away the process's grants and callbacks and runs it again from
+    /// the start of its image in flash, clearing its count of restarts after
+    /// faults. A terminated process first needs a new memory region, and
+    /// stays terminated if the pool has none large enough left.
+    pub unsafe fn restart(&mut self) -> Result<(), Error> {
+        if self.state == State::Terminated {
+            let load_info = load_info_of(self.text.data);
+            let size = try!(memory_size_for(&load_info)
+                                .map_err(|_| Error::OutOfMemory));
+            self.memory = try!(alloc_app_memory(size)
+                                   .ok_or(Error::OutOfMemory));
         }
+        self.restarts = 0;
+        self.restart_at = None;
+        self.free_kernel_memory();
+        self.load_image();
+        self.start();
+        Ok(())
+    }
+
+    /// Stops the process for good, releasing its grants and callbacks and
+    /// giving its memory region back to the app memory pool. Used both when
+    /// the app exits and when the kernel kills it.
+    pub unsafe fn terminate(&mut self) {
+        if self.state == State::Terminated {
+            return;
+        }
+        self.restart_at = None;
+        self.free_kernel_memory();
+        self.set_state(State::Terminated);
+        free_app_memory(self.memory);
+        self.memory = Slice { data: ptr::null(), len: 0 };
+    }
+
+    pub fn state(&self) -> State {
+        self.state
+    }
+
+    /// Moves the process to `next`.
+    ///
+    /// Panics if a process in its current state can't become `next` (see
+    /// `State::can_become`), since that means the kernel lost track of it.
+    pub fn set_state(&mut self, next: State) {
+        if !self.state.can_become(next) {
+            panic!("Process can't go from {:?} to {:?}", self.state, next);
+        }
+        self.state = next;
     }
 
     pub fn sbrk(&mut self, increment: isize) -> Result<*const u8, Error> {
@@ -211,8 +1241,10 @@ impl<'a> Process<'a> {
     pub fn brk(&mut self, new_break: *const u8) -> Result<*const u8, Error> {
         if new_break < self.mem_start() || new_break >= self.mem_end() {
             Err(Error::AddressOutOfBounds)
-        } else if new_break > self.kernel_memory_break {
+        } else if new_break > self.app_accessible_end() {
             Err(Error::OutOfMemory)
+        } else if new_break < self.exposed_end {
+            Err(Error::InUse)
         } else {
             let old_break = self.app_memory_break;
             self.app_memory_break = new_break;
@@ -220,31 +1252,138 @@ impl<'a> Process<'a> {
         }
     }
 
-    pub fn in_exposed_bounds(&self, buf_start_addr: *const u8, size: usize)
-            -> bool {
+    /// Checks that a buffer lies entirely in memory the process owns, i.e.
+    /// below its memory break. Everything above that is either kernel memory
+    /// (grants, the callback queue and container pointers) or may become
+    /// kernel memory, so must not be exposed to drivers as app buffers.
+    pub fn check_exposed_bounds(&self, buf_start_addr: *const u8, size: usize)
+            -> Result<(), Error> {
+        let buf_start = buf_start_addr as usize;
+        let buf_end = match buf_start.checked_add(size) {
+            Some(end) => end,
+            None => return Err(Error::AddressOverflow)
+        };
+
+        if buf_start < self.mem_start() as usize ||
+                buf_end > self.mem_end() as usize {
+            Err(Error::AddressOutOfBounds)
+        } else if buf_end > self.app_memory_break as usize {
+            Err(Error::KernelMemory)
+        } else {
+            Ok(())
+        }
+    }
 
-        let buf_end_addr = ((buf_start_addr as usize) + size) as *const u8;
+    /// Checks a buffer the app is passing to a driver like
+    /// `check_exposed_bounds`, and keeps the memory break from being lowered
+    /// below its end from now on.
+    pub fn expose(&mut self, buf_start_addr: *const u8, size: usize)
+            -> Result<(), Error> {
+        try!(self.check_exposed_bounds(buf_start_addr, size));
+        let buf_end = (buf_start_addr as usize + size) as *const u8;
+        self.exposed_end = cmp::max(self.exposed_end, buf_end);
+        Ok(())
+    }
 
-        let mem_end =
-            ((self.memory.data as usize) + self.memory.len) as *const u8;
+    fn grant_blocks(&self) -> GrantBlocks {
+        GrantBlocks {
+            next: self.kernel_memory_break as *mut GrantBlock,
+            end: self.grant_end
+        }
+    }
 
-        buf_start_addr >= self.memory.data && buf_end_addr <= mem_end
+    /// Allocates `size` bytes of grant memory on behalf of container
+    /// `owner`. Reuses the first freed block that is large enough, and
+    /// otherwise grows the grant region down towards the app's memory.
+    pub unsafe fn alloc(&mut self, size: usize, owner: usize)
+            -> Option<&mut [u8]> {
+        let size = grant_align(size);
+        let header_size = mem::size_of::<GrantBlock>();
+
+        let reusable = self.grant_blocks().find(|&block| {
+            (*block).owner == FREE_BLOCK && (*block).size >= size
+        });
+        let block = match reusable {
+            Some(block) => {
+                // Split off the rest of the block if it can hold anything
+                let spare = (*block).size - size;
+                if spare >= header_size + GRANT_ALIGN {
+                    let rest = grant_data(block).offset(size as isize)
+                                   as *mut GrantBlock;
+                    (*rest).size = spare - header_size;
+                    (*rest).owner = FREE_BLOCK;
+                    (*block).size = size;
+                }
+                block
+            },
+            None => {
+                let new_break = self.kernel_memory_break
+                                    .offset(-((header_size + size) as isize));
+                if new_break < self.mem_start() ||
+                        self.subregion_floor(new_break) < self.app_memory_break {
+                    return None;
+                }
+                self.kernel_memory_break = new_break;
+                let block = new_break as *mut GrantBlock;
+                (*block).size = size;
+                block
+            }
+        };
+        (*block).owner = owner;
+        Some(mem::transmute(Slice {
+            data: grant_data(block),
+            len: (*block).size
+        }))
     }
 
-    pub unsafe fn alloc(&mut self, size: usize) -> Option<&mut [u8]> {
-        let new_break = self.kernel_memory_break.offset(-(size as isize));
-        if new_break < self.app_memory_break {
-            None
-        } else {
-            self.kernel_memory_break = new_break;
-            Some(mem::transmute(Slice {
-                data: new_break as *mut u8,
-                len: size
-            }))
+    /// Returns a grant allocation made by `alloc`. Pointers to anything else,
+    /// such as buffers in the app's own memory, are ignored.
+    pub unsafe fn free<T>(&mut self, ptr: *mut T) {
+        let ptr = ptr as *mut u8;
+        if (ptr as *const u8) < self.kernel_memory_break ||
+                (ptr as *const u8) >= self.grant_end {
+            return;
+        }
+        let freed = self.grant_blocks().find(|&block| {
+            grant_data(block) == ptr && (*block).owner != FREE_BLOCK
+        });
+        if let Some(block) = freed {
+            (*block).owner = FREE_BLOCK;
+            self.coalesce_grants();
+        }
+    }
+
+    /// Merges neighbouring free grant blocks, and gives a free block at the
+    /// bottom of the grant region back by raising the kernel memory break.
+    unsafe fn coalesce_grants(&mut self) {
+        let mut run: Option<*mut GrantBlock> = None;
+        for block in self.grant_blocks() {
+            if (*block).owner != FREE_BLOCK {
+                run = None;
+            } else if let Some(first) = run {
+                (*first).size += mem::size_of::<GrantBlock>() + (*block).size;
+            } else {
+                run = Some(block);
+            }
+        }
+
+        let lowest = self.kernel_memory_break as *mut GrantBlock;
+        if self.kernel_memory_break < self.grant_end &&
+                (*lowest).owner == FREE_BLOCK {
+            self.kernel_memory_break =
+                grant_data(lowest).offset((*lowest).size as isize);
         }
     }
 
-    pub unsafe fn free<T>(&mut self, _: *mut T) {}
+    /// Bytes of grant memory, including block headers, currently allocated
+    /// by container `owner`.
+    pub fn grant_bytes(&self, owner: usize) -> usize {
+        self.grant_blocks().filter(|&block| unsafe {
+            (*block).owner == owner
+        }).fold(0, |total, block| unsafe {
+            total + mem::size_of::<GrantBlock>() + (*block).size
+        })
+    }
 
     pub unsafe fn container_for<T>(&mut self, container_num: usize)
             -> *mut *mut T {
@@ -259,7 +1398,7 @@ impl<'a> Process<'a> {
             -> Option<*mut T> {
         let ctr_ptr = self.container_for::<T>(container_num);
         if (*ctr_ptr).is_null() {
-            self.alloc(mem::size_of::<T>()).map(|root_arr| {
+            self.alloc(mem::size_of::<T>(), container_num).map(|root_arr| {
                 let root_ptr = root_arr.repr().data as *mut T;
                 *root_ptr = Default::default();
                 volatile_store(ctr_ptr, root_ptr);
@@ -304,15 +1443,110 @@ impl<'a> Process<'a> {
         intrinsics::volatile_load(&SYSCALL_FIRED) != 0
     }
 
+    pub unsafe fn app_fault(&self) -> bool {
+        intrinsics::volatile_load(&APP_FAULT) != 0
+    }
+
     /// Context switch to the process.
+    ///
+    /// If the process's stack pointer is out of bounds (see
+    /// `stack_in_bounds`), e.g. because its stack overflowed into its guard
+    /// region, it is not run, and is instead marked as having faulted (see
+    /// `app_fault`).
     pub unsafe fn switch_to(&mut self) {
-        if self.cur_stack < self.memory.data {
-            breakpoint();
+        if !self.stack_in_bounds() {
+            volatile_store(&mut SYSCALL_FIRED, 0);
+            volatile_store(&mut APP_FAULT, 1);
+            return;
         }
+        volatile_store(&mut PROCESS_STACK_BOUNDS,
+                       [self.stack_limit() as usize,
+                        self.app_accessible_end() as usize]);
         let psp = switch_to_user(self.cur_stack, self.memory.data);
         self.cur_stack = psp;
     }
 
+    /// Whether the registers stacked around the process's saved stack
+    /// pointer, eight words above it and eight below, lie between its stack
+    /// limit and the end of the memory it may access, i.e. whether they can
+    /// be trusted.
+    pub fn stack_in_bounds(&self) -> bool {
+        let sp = self.cur_stack as usize;
+        sp >= self.stack_limit() as usize + 32 &&
+            sp + 32 <= self.app_accessible_end() as usize
+    }
+
+    pub fn app_memory_break(&self) -> *const u8 {
+        self.app_memory_break
+    }
+
+    pub fn kernel_memory_break(&self) -> *const u8 {
+        self.kernel_memory_break
+    }
+
+    pub fn generation(&self) -> usize {
+        self.generation
+    }
+
+    pub fn priority(&self) -> usize {
+        self.priority
+    }
+
+    pub fn period_us(&self) -> usize {
+        self.period_us
+    }
+
+    pub fn budget_us(&self) -> usize {
+        self.budget_us
+    }
+
+    /// The app's name from its TBF header, or an empty string for images
+    /// without one.
+    pub fn name(&self) -> &str {
+        let len = self.name.iter().position(|&c| c == 0)
+                               .unwrap_or(TBF_NAME_LEN);
+        str::from_utf8(&self.name[..len]).unwrap_or("")
+    }
+
+    /// Number of callbacks the process can have queued.
+    pub fn callback_queue_len(&self) -> usize {
+        self.callback_queue_len
+    }
+
+    /// Number of callbacks dropped because the process's callback queue was
+    /// full since the last call.
+    pub fn take_dropped_callbacks(&mut self) -> usize {
+        let dropped = self.unreported_drops;
+        self.unreported_drops = 0;
+        dropped
+    }
+
+    /// Whether the process has anything to do: it is either running or
+    /// waiting with callbacks queued.
+    pub fn is_runnable(&self) -> bool {
+        match self.state {
+            State::Running => true,
+            State::Waiting => self.callbacks.has_elements(),
+            State::Stopped | State::StoppedWaiting => false,
+            State::Unstarted | State::Faulted | State::Terminated => false
+        }
+    }
+
+    pub fn cur_stack(&self) -> *const u8 {
+        self.cur_stack
+    }
+
+    /// Offset of `addr` from the start of the app's code, if it falls within
+    /// the app's text segment.
+    pub fn text_offset(&self, addr: usize) -> Option<usize> {
+        let text_end = self.text.data as usize + self.text.len;
+        if addr >= self.text_start as usize && addr < text_end {
+            Some(addr - self.text_start as usize)
+        } else {
+            None
+        }
+    }
+
     pub fn svc_number(&self) -> Option<u8> {
         let psp = self.cur_stack as *const *const u16;
         unsafe {
@@ -353,27 +1587,92 @@ impl<'a> Process<'a> {
         unsafe { volatile_load(pspr.offset(3)) }
     }
 
+    pub fn r12(&self) -> usize {
+        let pspr = self.cur_stack as *const usize;
+        unsafe { volatile_load(pspr.offset(4)) }
+    }
+
+    pub fn pc(&self) -> usize {
+        let pspr = self.cur_stack as *const usize;
+        unsafe { volatile_load(pspr.offset(6)) }
+    }
+
+    pub fn xpsr(&self) -> usize {
+        let pspr = self.cur_stack as *const usize;
+        unsafe { volatile_load(pspr.offset(7)) }
+    }
+
+}
+
+/// Lays out the kernel-owned top of a process's memory: one null container
+/// pointer per grant, followed by a callback queue `callback_len` long.
+/// Returns the resulting kernel memory break and the (empty) callback queue.
+unsafe fn init_kernel_memory<'a>(memory: &Slice<u8>, callback_len: usize)
+        -> (*const u8, RingBuffer<'a, Callback>) {
+    let mut kernel_memory_break = {
+        // make room for container pointers
+        let psz = mem::size_of::<*const usize>();
+        let num_ctrs = volatile_load(&container::CONTAINER_COUNTER);
+        let container_ptrs_size = num_ctrs * psz;
+        let res = memory.data.offset((memory.len - container_ptrs_size) as isize);
+        // set all ptrs to null
+        let opts : &mut [*const usize] = mem::transmute(Slice {
+            data: res as *mut *const usize,
+            len: num_ctrs
+        });
+        for opt in opts.iter_mut() {
+            *opt = ptr::null()
+        }
+        res
+    };
+
+    // Take callback buffer from of memory. A ring buffer always keeps one
+    // slot empty, so it needs one more than the callbacks it holds.
+    let callback_buf_len = callback_len + 1;
+    let callback_size = mem::size_of::<Option<Callback>>();
+    let callback_offset = callback_buf_len * callback_size;
+    // Set kernel break to beginning of callback buffer
+    kernel_memory_break =
+        kernel_memory_break.offset(-(callback_offset as isize));
+    let callback_buf = mem::transmute(Slice {
+        data: kernel_memory_break as *const Option<Callback>,
+        len: callback_buf_len
+    });
+
+    // Grant blocks start below the callback buffer
+    kernel_memory_break =
+        (kernel_memory_break as usize & !(GRANT_ALIGN - 1)) as *const u8;
+
+    (kernel_memory_break, RingBuffer::new(callback_buf))
 }
 
 struct LoadResult {
     text_start: *const u8,
     text_len: usize,
     init_fn: usize,
-    app_mem_start: *const u8
+    app_mem_start: *const u8,
+    stack_size: usize,
+    priority: usize,
+    period_us: usize,
+    budget_us: usize
 }
 
-unsafe fn load(start_addr: *const usize, mem_base: *const u8) -> LoadResult {
+/// Loads the app `load_info` describes, whose relocation data starts at
+/// `start_addr`, into the memory at `mem_base`.
+unsafe fn load(load_info: &LoadInfo, start_addr: *const u8,
+               mem_base: *const u8) -> LoadResult {
     let mut result = LoadResult {
-        text_start: start_addr as *const u8,
+        text_start: start_addr,
         text_len: 0,
         init_fn: 0,
-        app_mem_start: 0 as *const u8
+        app_mem_start: 0 as *const u8,
+        stack_size: 0,
+        priority: 0,
+        period_us: 0,
+        budget_us: 0
     };
 
-    let mut start_addr = start_addr as *const u8;
-    let load_info : &LoadInfo = mem::transmute(start_addr);
-    start_addr = start_addr.offset(mem::size_of::<LoadInfo>() as isize);
-
+    let mut start_addr = start_addr;
     let rel_data : &mut [usize] = mem::transmute(Slice{
         data: start_addr,
         len: load_info.rel_data_size / 4
@@ -434,6 +1733,11 @@ unsafe fn load(start_addr: *const usize, mem_base: *const u8) -> LoadResult {
     // Entry point is offset from app code
     result.init_fn = start_addr as usize + load_info.entry_loc;
 
+    result.stack_size = load_info.stack_size();
+    result.priority = load_info.priority;
+    result.period_us = load_info.period_us;
+    result.budget_us = load_info.budget_us;
+
     let mut aligned_mem_start = load_info.bss_end_offset as isize;
     aligned_mem_start += (8 - (aligned_mem_start % 8)) % 8;
     result.app_mem_start = mem_base.offset(aligned_mem_start);
@@ -0,0 +1,279 @@
+//! Policies for choosing which process runs next.
+//!
+//! The main loop asks the platform's `Scheduler` for a process to run and how
+//! long its timeslice is, runs it until the timeslice expires, it waits for a
+//! callback or an interrupt is pending, and then asks again. Once the process
+//! stops running the scheduler is told how much of its timeslice it used.
+
+use core::cmp;
+use process::{Process, State, NUM_PROCS};
+
+/// Timeslice, in microseconds, given to processes by the schedulers here.
+pub const DEFAULT_TIMESLICE_US: u32 = 10000;
+
+pub trait Scheduler {
+    /// Picks the next process to run from `processes`, returning its index and
+    /// timeslice in microseconds, or `None` if no process is runnable.
+    fn next(&mut self, processes: &[Option<Process>]) -> Option<(usize, u32)>;
+
+    /// Called after the process at `idx` ran for `used_us` microseconds.
+    fn ran(&mut self, _idx: usize, _used_us: u32) {}
+
+    /// The clock time at which the scheduler next lets a process run that it
+    /// is holding back although it is runnable, if any. The kernel wakes up
+    /// then rather than sleeping until the next interrupt.
+    fn next_release(&self, _processes: &[Option<Process>]) -> Option<u32> {
+        None
+    }
+
+    /// The number of deadlines the process at `idx` has missed, for
+    /// schedulers that give processes deadlines.
+    fn deadline_misses(&self, _idx: usize) -> usize {
+        0
+    }
+}
+
+/// A free-running counter that schedulers can use to tell the time.
+pub trait Clock {
+    /// The current value of the counter. Wraps around on overflow.
+    fn now(&self) -> u32;
+
+    /// Counter ticks per second.
+    fn frequency(&self) -> u32;
+}
+
+/// Runs each runnable process in turn.
+pub struct RoundRobin {
+    next_idx: usize
+}
+
+impl RoundRobin {
+    pub const fn new() -> RoundRobin {
+        RoundRobin { next_idx: 0 }
+    }
+}
+
+impl Scheduler for RoundRobin {
+    fn next(&mut self, processes: &[Option<Process>]) -> Option<(usize, u32)> {
+        let len = processes.len();
+        for offset in 0..len {
+            let idx = (self.next_idx + offset) % len;
+            let runnable = processes[idx].as_ref()
+                                         .map_or(false, |p| p.is_runnable());
+            if runnable {
+                self.next_idx = (idx + 1) % len;
+                return Some((idx, DEFAULT_TIMESLICE_US));
+            }
+        }
+        None
+    }
+}
+
+/// Always runs the runnable process with the highest priority, as set in its
+/// app header. Processes with equal priority take turns, so if every app has
+/// the same priority this behaves like `RoundRobin`.
+pub struct FixedPriority {
+    last_idx: usize
+}
+
+impl FixedPriority {
+    pub const fn new() -> FixedPriority {
+        FixedPriority { last_idx: 0 }
+    }
+}
+
+impl FixedPriority {
+    /// Picks the highest priority process that is runnable and for which
+    /// `eligible` holds.
+    fn pick<F>(&mut self, processes: &[Option<Process>], eligible: F)
+            -> Option<usize> where F: Fn(&Process) -> bool {
+        let len = processes.len();
+        let mut best: Option<(usize, usize)> = None;
+        // Start after the last process run so equal priorities take turns
+        for offset in 1..(len + 1) {
+            let idx = (self.last_idx + offset) % len;
+            if let Some(ref p) = processes[idx] {
+                let higher = best.map_or(true, |(_, prio)| p.priority() > prio);
+                if p.is_runnable() && eligible(p) && higher {
+                    best = Some((idx, p.priority()));
+                }
+            }
+        }
+        best.map(|(idx, _)| {
+            self.last_idx = idx;
+            idx
+        })
+    }
+}
+
+impl Scheduler for FixedPriority {
+    fn next(&mut self, processes: &[Option<Process>]) -> Option<(usize, u32)> {
+        self.pick(processes, |_| true).map(|idx| (idx, DEFAULT_TIMESLICE_US))
+    }
+}
+
+/// The current job of a periodic process. Times are in clock ticks.
+#[derive(Copy,Clone)]
+struct Job {
+    started: bool,
+    /// The process's generation when the job started. A restarted or
+    /// terminated process starts over with a new job.
+    generation: usize,
+    release: u32,
+    deadline: u32,
+    remaining_us: u32,
+    deadline_misses: usize
+}
+
+const NO_JOB: Job = Job {
+    started: false,
+    generation: 0,
+    release: 0,
+    deadline: 0,
+    remaining_us: 0,
+    deadline_misses: 0
+};
+
+/// Whether clock time `a` comes before `b`, allowing for wrap-around.
+pub fn before(a: u32, b: u32) -> bool {
+    (a.wrapping_sub(b) as i32) < 0
+}
+
+/// Earliest-deadline-first scheduling for periodic apps.
+///
+/// An app that sets a period in its header gets a new job every period, due
+/// at the start of the next one. Runnable periodic apps that have budget left
+/// in the current period run in order of their deadlines, and their
+/// timeslices are cut short so they cannot run over budget. A periodic app
+/// that is still runnable when its deadline passes has missed it.
+///
+/// Apps without a period run in the background, by fixed priority, when no
+/// periodic app needs the CPU. With no periodic apps this behaves like
+/// `FixedPriority`.
+pub struct EarliestDeadlineFirst<'a> {
+    clock: &'a Clock,
+    jobs: [Job; NUM_PROCS],
+    background: FixedPriority
+}
+
+impl<'a> EarliestDeadlineFirst<'a> {
+    pub const fn new(clock: &'a Clock) -> EarliestDeadlineFirst<'a> {
+        EarliestDeadlineFirst {
+            clock: clock,
+            jobs: [NO_JOB; NUM_PROCS],
+            background: FixedPriority::new()
+        }
+    }
+
+    fn us_to_ticks(&self, us: usize) -> u32 {
+        (us as u64 * self.clock.frequency() as u64 / 1000000) as u32
+    }
+
+    /// Starts jobs for newly started periodic processes and moves on to the
+    /// next period for those whose deadline has passed.
+    fn release_jobs(&mut self, processes: &[Option<Process>], now: u32) {
+        for (idx, slot) in processes.iter().enumerate().take(NUM_PROCS) {
+            let p = match *slot {
+                Some(ref p) if p.period_us() > 0 => p,
+                _ => continue
+            };
+            let period = cmp::max(self.us_to_ticks(p.period_us()), 1);
+            let job = &mut self.jobs[idx];
+            // Processes that haven't started, faulted or were terminated get
+            // a fresh job once they run again, as do restarted ones
+            match p.state() {
+                State::Unstarted | State::Faulted | State::Terminated => {
+                    job.started = false;
+                    continue;
+                },
+                _ => {}
+            }
+            if !job.started || job.generation != p.generation() {
+                job.started = true;
+                job.generation = p.generation();
+                job.deadline = now;
+            } else if before(now, job.deadline) {
+                continue;
+            } else if p.is_runnable() {
+                job.deadline_misses += 1;
+            }
+            // Skip any periods that passed entirely while nothing checked
+            while !before(now, job.deadline) {
+                job.release = job.deadline;
+                job.deadline = job.deadline.wrapping_add(period);
+            }
+            // Without a budget the app may use its whole period
+            job.remaining_us = if p.budget_us() > 0 {
+                p.budget_us() as u32
+            } else {
+                p.period_us() as u32
+            };
+        }
+    }
+}
+
+impl<'a> Scheduler for EarliestDeadlineFirst<'a> {
+    fn next(&mut self, processes: &[Option<Process>]) -> Option<(usize, u32)> {
+        let now = self.clock.now();
+        self.release_jobs(processes, now);
+
+        let mut earliest: Option<usize> = None;
+        for (idx, slot) in processes.iter().enumerate().take(NUM_PROCS) {
+            let job = &self.jobs[idx];
+            let ready = slot.as_ref().map_or(false, |p| {
+                p.period_us() > 0 && p.is_runnable()
+            });
+            if ready && job.remaining_us > 0 && !before(now, job.release) {
+                let sooner = earliest.map_or(true, |e| {
+                    before(job.deadline, self.jobs[e].deadline)
+                });
+                if sooner {
+                    earliest = Some(idx);
+                }
+            }
+        }
+
+        match earliest {
+            Some(idx) => {
+                let remaining = self.jobs[idx].remaining_us;
+                Some((idx, if remaining < DEFAULT_TIMESLICE_US {
+                    remaining
+                } else {
+                    DEFAULT_TIMESLICE_US
+                }))
+            },
+            None => {
+                self.background.pick(processes, |p| p.period_us() == 0)
+                               .map(|idx| (idx, DEFAULT_TIMESLICE_US))
+            }
+        }
+    }
+
+    fn ran(&mut self, idx: usize, used_us: u32) {
+        if let Some(job) = self.jobs.get_mut(idx) {
+            job.remaining_us = job.remaining_us.saturating_sub(used_us);
+        }
+    }
+
+    /// The next release of a runnable periodic process that has used up its
+    /// budget.
+    fn next_release(&self, processes: &[Option<Process>]) -> Option<u32> {
+        let mut next: Option<u32> = None;
+        for (idx, slot) in processes.iter().enumerate().take(NUM_PROCS) {
+            let job = &self.jobs[idx];
+            let waiting = slot.as_ref().map_or(false, |p| {
+                p.period_us() > 0 && p.is_runnable() &&
+                    job.generation == p.generation()
+            });
+            if waiting && job.started && job.remaining_us == 0 &&
+                    next.map_or(true, |n| before(job.deadline, n)) {
+                next = Some(job.deadline);
+            }
+        }
+        next
+    }
+
+    fn deadline_misses(&self, idx: usize) -> usize {
+        self.jobs.get(idx).map_or(0, |job| job.deadline_misses)
+    }
+}
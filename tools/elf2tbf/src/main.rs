@@ -10,6 +10,32 @@ use std::io;
 use std::io::Write;
 
 
+/// First word of the TBF header, "TOCK" in ASCII.
+const TBF_MAGIC: u32 = 0x4B434F54;
+
+/// Version of the TBF header written.
+const TBF_VERSION: u32 = 1;
+
+/// `TbfHeader::flags` bit set for apps the kernel should load.
+const TBF_FLAG_ENABLED: u32 = 1 << 0;
+
+/// Length of the app name in the TBF header.
+const TBF_NAME_LEN: usize = 16;
+
+/// Header between the image's length word and `LoadInfo`. Kernels also load
+/// images without it, which start with `LoadInfo` right after the length.
+#[repr(C,packed)]
+struct TbfHeader {
+    magic: u32,              /* TBF_MAGIC */
+    version: u32,            /* Header format version */
+    header_size: u32,        /* Size of this header, LoadInfo follows it */
+    flags: u32,              /* TBF_FLAG_* */
+    checksum: u32,           /* XOR of the image's words after the header */
+    app_heap_size: u32,      /* Heap requested by the app */
+    kernel_heap_size: u32,   /* Grant memory requested for the app */
+    name: [u8; TBF_NAME_LEN] /* App name, padded with NULs */
+}
+
 #[repr(C,packed)]
 #[derive(Debug)]
 struct LoadInfo {
@@ -20,7 +46,40 @@ struct LoadInfo {
     got_start_offset: u32, /* Offset in memory to start of GOT */
     got_end_offset: u32,   /* Offset in memory to end of GOT */
     bss_start_offset: u32, /* Offset in memory to start of BSS */
-    bss_end_offset: u32    /* Offset in memory to end of BSS */
+    bss_end_offset: u32,   /* Offset in memory to end of BSS */
+    min_app_ram_size: u32, /* RAM the app needs, including stack and heaps */
+    stack_size: u32,       /* Size of the app's stack */
+    priority: u32,         /* Scheduling priority, higher runs first */
+    period_us: u32,        /* Period for deadline scheduling, 0 if none */
+    budget_us: u32,        /* CPU time the app may use each period */
+    callback_queue_len: u32 /* Callback queue length, 0 for default */
+}
+
+/// Size of the guard region the kernel puts below each app's stack. The guard
+/// is aligned to its size, so it can cost up to twice this much RAM.
+const STACK_GUARD_SIZE: u32 = 32;
+
+/// Sizes of the memory an app needs beyond its GOT, data and BSS.
+struct MemorySizes {
+    stack: u32,
+    app_heap: u32,
+    kernel_heap: u32,
+    /// Number of callbacks the kernel queues for the app, 0 for the kernel's
+    /// default.
+    callback_queue: u32
+}
+
+/// What the TBF header says about the app besides its memory sizes.
+struct Metadata {
+    name: String,
+    enabled: bool
+}
+
+/// How the kernel should schedule the app.
+struct Scheduling {
+    priority: u32,
+    period_us: u32,
+    budget_us: u32
 }
 
 fn main() {
@@ -28,12 +87,48 @@ fn main() {
     let program = args[0].clone();
     let mut opts = Options::new();
     opts.optopt("o", "", "set output file name", "OUTFILE");
+    opts.optopt("", "stack", "set the app's stack size (default 2048)", "SIZE");
+    opts.optopt("", "app-heap",
+                "set the app's heap size (default 1024)", "SIZE");
+    opts.optopt("", "kernel-heap",
+                "set the kernel's heap size for the app (default 1024)", "SIZE");
+    opts.optopt("", "callback-queue",
+                "set how many callbacks the kernel queues for the app \
+                 (default: the kernel's)", "LENGTH");
+    opts.optopt("", "name",
+                "set the app's name, truncated to 16 bytes (default: the \
+                 input file's name)", "NAME");
+    opts.optflag("", "disabled",
+                 "mark the app as disabled, so the kernel doesn't start it");
+    opts.optopt("", "priority",
+                "set the app's scheduling priority, higher runs first (default 0)",
+                "PRIORITY");
+    opts.optopt("", "period",
+                "run the app every PERIOD microseconds by earliest deadline",
+                "PERIOD");
+    opts.optopt("", "budget",
+                "limit the app to BUDGET microseconds of CPU time per period",
+                "BUDGET");
 
     let matches = match opts.parse(&args[1..]) {
         Ok(m) => { m }
         Err(f) => { panic!(f.to_string()) }
     };
     let output = matches.opt_str("o");
+    let sizes = MemorySizes {
+        stack: parse_number(&matches, "stack", 2048),
+        app_heap: parse_number(&matches, "app-heap", 1024),
+        kernel_heap: parse_number(&matches, "kernel-heap", 1024),
+        callback_queue: parse_number(&matches, "callback-queue", 0)
+    };
+    let scheduling = Scheduling {
+        priority: parse_number(&matches, "priority", 0),
+        period_us: parse_number(&matches, "period", 0),
+        budget_us: parse_number(&matches, "budget", 0)
+    };
+    if scheduling.budget_us > 0 && scheduling.period_us == 0 {
+        panic!("--budget needs a --period");
+    }
     let input = if !matches.free.is_empty() {
         matches.free[0].clone()
     } else {
@@ -43,6 +138,14 @@ fn main() {
 
 
     let path = Path::new(&input);
+    let metadata = Metadata {
+        name: matches.opt_str("name").unwrap_or_else(|| {
+            path.file_stem().map_or(String::new(), |stem| {
+                stem.to_string_lossy().into_owned()
+            })
+        }),
+        enabled: !matches.opt_present("disabled")
+    };
     let file = match elf::File::open_path(&path) {
         Ok(f) => f,
         Err(e) => panic!("Error: {:?}", e),
@@ -51,10 +154,11 @@ fn main() {
     match output {
         None => {
             let mut out = io::stdout();
-            do_work(&file, &mut out)
+            do_work(&file, &mut out, &sizes, &scheduling, &metadata)
         }
         Some(name) => match File::create(Path::new(&name)) {
-            Ok(mut f) => do_work(&file, &mut f),
+            Ok(mut f) => do_work(&file, &mut f, &sizes, &scheduling,
+                                 &metadata),
             Err(e) => panic!("Error: {:?}", e),
         }
     };
@@ -62,6 +166,16 @@ fn main() {
 
 }
 
+fn parse_number(matches: &getopts::Matches, name: &str, default: u32) -> u32 {
+    match matches.opt_str(name) {
+        None => default,
+        Some(s) => match s.parse() {
+            Ok(size) => size,
+            Err(e) => panic!("Invalid --{} \"{}\": {}", name, s, e),
+        }
+    }
+}
+
 fn print_usage(program: &str, opts: Options) {
     let brief = format!("Usage: {} [-o OUTFILE] FILE", program);
     print!("{}", opts.usage(&brief));
@@ -76,7 +190,8 @@ fn get_section<'a>(input: &'a elf::File, name: &str) -> &'a elf::Section {
     }
 }
 
-fn do_work(input: &elf::File, output: &mut Write) {
+fn do_work(input: &elf::File, output: &mut Write, sizes: &MemorySizes,
+           scheduling: &Scheduling, metadata: &Metadata) {
     let (rel_data_size, rel_data) = match input.sections.iter()
             .find(|section| section.shdr.name == ".rel.data".as_ref()) {
         Some(section) => {
@@ -90,6 +205,12 @@ fn do_work(input: &elf::File, output: &mut Write) {
     let data = get_section(input, ".data");
     let bss = get_section(input, ".bss");
 
+    // Everything from the start of app memory to the end of BSS, followed by
+    // the (aligned) stack guard, the stack and both heaps.
+    let bss_end = (bss.shdr.addr + bss.shdr.size) as u32;
+    let min_app_ram_size = bss_end + 2 * STACK_GUARD_SIZE + sizes.stack +
+        sizes.app_heap + sizes.kernel_heap;
+
     let load_info = LoadInfo {
         rel_data_size: rel_data_size as u32,
         entry_loc: (input.ehdr.entry ^ 0x80000000) as u32,
@@ -98,7 +219,13 @@ fn do_work(input: &elf::File, output: &mut Write) {
         got_start_offset: 0,
         got_end_offset: got.shdr.size as u32,
         bss_start_offset: bss.shdr.addr as u32,
-        bss_end_offset: (bss.shdr.addr + bss.shdr.size) as u32
+        bss_end_offset: (bss.shdr.addr + bss.shdr.size) as u32,
+        min_app_ram_size: min_app_ram_size,
+        stack_size: sizes.stack,
+        priority: scheduling.priority,
+        period_us: scheduling.period_us,
+        budget_us: scheduling.budget_us,
+        callback_queue_len: sizes.callback_queue
     };
 
     let load_info_bytes : &[u8] = unsafe {
@@ -108,13 +235,17 @@ fn do_work(input: &elf::File, output: &mut Write) {
         })
     };
 
+    // Everything after the TBF header, which its checksum covers
+    let mut body: Vec<u8> = Vec::new();
+    body.extend(load_info_bytes.iter().cloned());
+    body.extend(rel_data.iter().cloned());
+    body.extend(text.data.iter().cloned());
+    body.extend(got.data.iter().cloned());
+    body.extend(data.data.iter().cloned());
+
+    let header_size = std::mem::size_of::<TbfHeader>();
     let mut total_len : u32 =
-        (std::mem::size_of::<u32>() +
-        load_info_bytes.as_ref().len() +
-        rel_data.len() +
-        text.data.len() +
-        got.data.len() +
-        data.data.len()) as u32;
+        (std::mem::size_of::<u32>() + header_size + body.len()) as u32;
 
     let pad = if total_len.count_ones() > 1 {
         let power2len = 1 << (32 - total_len.leading_zeros());
@@ -123,6 +254,37 @@ fn do_work(input: &elf::File, output: &mut Write) {
         0
     };
     total_len = total_len + pad;
+    for _ in 0..pad {
+        body.push(0);
+    }
+
+    let checksum = body.chunks(4).fold(0, |checksum, word| {
+        checksum ^ (word[0] as u32 | (word[1] as u32) << 8 |
+                    (word[2] as u32) << 16 | (word[3] as u32) << 24)
+    });
+
+    let mut name = [0; TBF_NAME_LEN];
+    for (i, c) in metadata.name.bytes().take(TBF_NAME_LEN).enumerate() {
+        name[i] = c;
+    }
+
+    let header = TbfHeader {
+        magic: TBF_MAGIC,
+        version: TBF_VERSION,
+        header_size: header_size as u32,
+        flags: if metadata.enabled { TBF_FLAG_ENABLED } else { 0 },
+        checksum: checksum,
+        app_heap_size: sizes.app_heap,
+        kernel_heap_size: sizes.kernel_heap,
+        name: name
+    };
+
+    let header_bytes : &[u8] = unsafe {
+        std::mem::transmute(std::raw::Slice {
+            data: &header,
+            len: header_size
+        })
+    };
 
     let total_len_buf : &[u8; 4] = unsafe {
         std::mem::transmute(&total_len)
@@ -130,16 +292,7 @@ fn do_work(input: &elf::File, output: &mut Write) {
 
     let _ = output.write(total_len_buf);
 
-    let _ = output.write(load_info_bytes);
+    let _ = output.write(header_bytes);
 
-    let _ = output.write(rel_data.as_ref());
-
-    let _ = output.write(text.data.as_ref());
-    let _ = output.write(got.data.as_ref());
-    let _ = output.write(data.data.as_ref());
-
-    for _ in 0..pad {
-        let _ = output.write(&[0]);
-    }
+    let _ = output.write(&body);
 }
-
@@ -0,0 +1,69 @@
+#!/usr/bin/env python2
+
+# Decodes a system call trace dumped by the kernel shell's `!trace` command.
+#
+# Capture the console output to a file (e.g. with `screen -L` or
+# `cat /dev/ttyUSB0 > trace.bin`) while running `!trace`, then run:
+#
+#   decode.py trace.bin
+#
+# With no file, the capture is read from standard input. The format is
+# described in src/main/trace.rs.
+
+import struct
+import sys
+
+MAGIC = b"TRC1"
+HEADER = struct.Struct("<II")
+RECORD = struct.Struct("<IBBBxIIIIi")
+
+# Set in a record's flags if the call didn't return to the app
+NO_RETURN = 0x1
+
+SYSCALLS = {
+    0: "wait",
+    1: "subscribe",
+    2: "command",
+    3: "allow",
+    4: "memop",
+    5: "exit",
+}
+
+if len(sys.argv) > 1:
+    f = open(sys.argv[1], "rb")
+    data = f.read()
+    f.close()
+else:
+    data = sys.stdin.read()
+
+start = data.rfind(MAGIC)
+if start < 0:
+    print("No trace found")
+    sys.exit(1)
+start += len(MAGIC)
+
+count, frequency = HEADER.unpack_from(data, start)
+start += HEADER.size
+if len(data) < start + count * RECORD.size:
+    print("Trace is truncated: expected %d records" % count)
+    sys.exit(1)
+
+print("%10s %3s %-10s %10s %10s %10s %10s %10s" %
+      ("Time (ms)", "App", "Syscall", "r0", "r1", "r2", "r3", "Returned"))
+
+first = None
+for i in range(count):
+    (timestamp, app, svc, flags, r0, r1, r2, r3, ret) = \
+        RECORD.unpack_from(data, start + i * RECORD.size)
+    if first is None:
+        first = timestamp
+    # Timestamps are 32-bit tick counts, so allow for them wrapping around
+    ms = ((timestamp - first) & 0xffffffff) * 1000.0 / frequency
+    name = SYSCALLS.get(svc, "svc %d" % svc)
+    # WAIT and EXIT don't return to the app
+    if flags & NO_RETURN:
+        returned = "-"
+    else:
+        returned = "%d" % ret
+    print("%10.3f %3d %-10s 0x%08x 0x%08x 0x%08x 0x%08x %10s" %
+          (ms, app, name, r0, r1, r2, r3, returned))